@@ -1,2 +1,5 @@
+pub mod modem;
 pub mod sp;
-pub mod sp_list;
\ No newline at end of file
+pub mod sp_list;
+
+pub use serialport;
@@ -0,0 +1,329 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{ErrorKind, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use anyhow::{Context, format_err, Result};
+use serde::{Deserialize, Serialize};
+
+pub mod xmodem;
+pub mod ymodem;
+pub mod zmodem;
+
+pub const SOH: u8 = 0x01;
+pub const STX: u8 = 0x02;
+pub const EOT: u8 = 0x04;
+pub const ACK: u8 = 0x06;
+pub const BS: u8 = 0x08;
+pub const NAK: u8 = 0x15;
+pub const CAN: u8 = 0x18;
+pub const SUB: u8 = 0x1A;
+pub const CRC: u8 = b'C';
+
+// 传输协议
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModemProtocol {
+    // XMODEM 校验和
+    Xmodem,
+    // XMODEM CRC16
+    XmodemCrc,
+    // XMODEM-1K
+    Xmodem1k,
+    Ymodem,
+    Zmodem,
+}
+
+// 重传原因分类
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RetryKind {
+    // 数据校验失败，含对方校验失败后要求重传
+    Checksum,
+    // 收到无效应答
+    Framing,
+    // 等待超时
+    Timeout,
+}
+
+// 传输过程事件
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ModemEvent {
+    // 开始传输一个文件
+    File { name: String, size: u64 },
+    // 当前文件进度
+    Progress { done: u64, total: u64 },
+    // 重传
+    Retry { count: u32, kind: RetryKind, reason: String },
+}
+
+pub trait ReadSeek: Read + Seek {}
+impl<T: Read + Seek> ReadSeek for T {}
+
+// 待发送文件
+pub struct ModemFile {
+    pub name: String,
+    pub size: u64,
+    // 修改时间，unix 秒
+    pub modified: u64,
+    pub data: Box<dyn ReadSeek + Send>,
+}
+
+impl ModemFile {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path).with_context(|| format!("打开文件失败：{}", path.display()))?;
+        let meta = file.metadata()?;
+        let name = path.file_name().context("文件名无效")?.to_string_lossy().to_string();
+        let modified = meta.modified().ok()
+            .and_then(|v| v.duration_since(UNIX_EPOCH).ok())
+            .map(|v| v.as_secs())
+            .unwrap_or(0);
+        Ok(Self {
+            name,
+            size: meta.len(),
+            modified,
+            data: Box::new(file),
+        })
+    }
+
+    // YMODEM/ZMODEM 文件头："文件名\0大小 修改时间(八进制)\0"
+    fn header(&self) -> Vec<u8> {
+        let mut v = Vec::from(self.name.as_bytes());
+        v.push(0);
+        v.extend_from_slice(format!("{} {:o}", self.size, self.modified).as_bytes());
+        v.push(0);
+        v
+    }
+}
+
+// 解析文件头，返回 (文件名, 大小, 修改时间)，大小字段可省略
+fn parse_file_header(data: &[u8]) -> Option<(String, Option<u64>, u64)> {
+    let mut parts = data.split(|v| *v == 0);
+    let name = String::from_utf8_lossy(parts.next()?).to_string();
+    if name.is_empty() {
+        return None;
+    }
+    let info = String::from_utf8_lossy(parts.next().unwrap_or_default()).to_string();
+    let mut info = info.split_whitespace();
+    let size = info.next().and_then(|v| v.parse::<u64>().ok());
+    let modified = info.next().and_then(|v| u64::from_str_radix(v, 8).ok()).unwrap_or(0);
+    Some((name, size, modified))
+}
+
+// 在目录下创建接收文件，只保留文件名部分
+fn create_recv_file(dir: &Path, name: &str) -> Result<(File, PathBuf)> {
+    let name = Path::new(name).file_name().context("文件名无效")?;
+    let path = dir.join(name);
+    let file = File::create(&path).with_context(|| format!("创建文件失败：{}", path.display()))?;
+    Ok((file, path))
+}
+
+// 写完后恢复发送方给出的修改时间
+fn set_recv_file_time(file: &File, modified: u64) {
+    if modified > 0 {
+        file.set_modified(UNIX_EPOCH + Duration::from_secs(modified)).unwrap_or_default();
+    }
+}
+
+// 尽量读满缓冲区，返回实际读取字节数
+fn read_full(data: &mut dyn Read, buf: &mut [u8]) -> Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        match data.read(&mut buf[n..]) {
+            Ok(0) => break,
+            Ok(x) => n += x,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(n)
+}
+
+pub fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |a, v| a.wrapping_add(*v))
+}
+
+// CRC16-CCITT (XMODEM)，多项式 0x1021，初值 0
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for v in data {
+        crc ^= (*v as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+// CRC32 (IEEE 802.3)
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for v in data {
+        crc ^= *v as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+// 文件传输协议的公共部分：带超时的字节读取、取消、事件回调
+// port 的读取超时应返回 ErrorKind::TimedOut（与 serialport 行为一致）
+pub struct Modem<P: Read + Write> {
+    port: P,
+    buf: VecDeque<u8>,
+    cancel: Arc<AtomicBool>,
+    handler: Box<dyn FnMut(ModemEvent)>,
+    max_retries: u32,
+    retries: u32,
+}
+
+impl<P: Read + Write> Modem<P> {
+    pub fn new(port: P) -> Self {
+        Self {
+            port,
+            buf: VecDeque::new(),
+            cancel: Arc::new(AtomicBool::new(false)),
+            handler: Box::new(|_| {}),
+            max_retries: 10,
+            retries: 0,
+        }
+    }
+
+    pub fn set_cancel(&mut self, cancel: Arc<AtomicBool>) {
+        self.cancel = cancel;
+    }
+
+    pub fn set_handler(&mut self, handler: impl FnMut(ModemEvent) + 'static) {
+        self.handler = Box::new(handler);
+    }
+
+    pub fn set_max_retries(&mut self, max_retries: u32) {
+        self.max_retries = max_retries;
+    }
+
+    pub fn into_inner(self) -> P {
+        self.port
+    }
+
+    fn emit(&mut self, event: ModemEvent) {
+        (self.handler)(event);
+    }
+
+    // 记录一次重传，超过次数后中止传输
+    fn retry(&mut self, kind: RetryKind, reason: &str) -> Result<()> {
+        self.retries += 1;
+        self.emit(ModemEvent::Retry { count: self.retries, kind, reason: reason.to_string() });
+        if self.retries > self.max_retries {
+            self.abort();
+            return Err(format_err!("重试次数过多：{reason}"));
+        }
+        Ok(())
+    }
+
+    fn reset_retry(&mut self) {
+        self.retries = 0;
+    }
+
+    // 通知对方取消传输
+    fn abort(&mut self) {
+        let mut v = [CAN; 16];
+        v[8..].fill(BS);
+        self.write_all(&v).unwrap_or_default();
+    }
+
+    fn check_cancel(&mut self) -> Result<()> {
+        if self.cancel.load(Ordering::Relaxed) {
+            self.abort();
+            return Err(format_err!("传输已取消"));
+        }
+        Ok(())
+    }
+
+    fn write_all(&mut self, data: &[u8]) -> Result<()> {
+        self.port.write_all(data)?;
+        Ok(())
+    }
+
+    // 从串口读取一次数据到内部缓冲区，超时返回 false
+    fn fill(&mut self) -> Result<bool> {
+        let mut tmp = [0u8; 1024];
+        match self.port.read(&mut tmp) {
+            Ok(0) => {
+                std::thread::sleep(Duration::from_millis(1));
+                Ok(false)
+            }
+            Ok(n) => {
+                self.buf.extend(&tmp[..n]);
+                Ok(true)
+            }
+            Err(ref e) if matches!(e.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock | ErrorKind::Interrupted) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn read_byte(&mut self, timeout: Duration) -> Result<Option<u8>> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(v) = self.buf.pop_front() {
+                return Ok(Some(v));
+            }
+            self.check_cancel()?;
+            if !self.fill()? && Instant::now() >= deadline {
+                return Ok(None);
+            }
+        }
+    }
+
+    fn unread_byte(&mut self, v: u8) {
+        self.buf.push_front(v);
+    }
+
+    // 丢弃线路上残留的数据
+    fn purge(&mut self) -> Result<()> {
+        self.buf.clear();
+        while self.read_byte(Duration::from_millis(50))?.is_some() {}
+        Ok(())
+    }
+
+    // 收到 CAN 后再确认一个 CAN，避免线路噪声误判
+    fn is_cancelled_by_peer(&mut self) -> Result<bool> {
+        Ok(self.read_byte(Duration::from_secs(1))? == Some(CAN))
+    }
+}
+
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+
+    // 一对互联的虚拟串口，用于端到端测试
+    // 任一端关闭后另一端会读到挂断，测试中两端都结束后再释放
+    #[cfg(unix)]
+    pub(crate) fn pty_pair() -> (serialport::TTYPort, serialport::TTYPort) {
+        use serialport::SerialPort;
+        let (mut a, mut b) = serialport::TTYPort::pair().unwrap();
+        a.set_timeout(Duration::from_millis(20)).unwrap();
+        b.set_timeout(Duration::from_millis(20)).unwrap();
+        (a, b)
+    }
+
+    #[test]
+    fn test_crc() {
+        assert_eq!(crc16(b"123456789"), 0x31C3);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(checksum(&[0xFF, 0x02]), 0x01);
+    }
+
+    #[test]
+    fn test_parse_file_header() {
+        assert_eq!(parse_file_header(b"a.bin\x001024 14707345566\x00\x00\x00"), Some(("a.bin".to_string(), Some(1024), 0o14707345566)));
+        assert_eq!(parse_file_header(b"a.bin\x00\x00"), Some(("a.bin".to_string(), None, 0)));
+        assert_eq!(parse_file_header(b"a.bin\x000\x00"), Some(("a.bin".to_string(), Some(0), 0)));
+        assert_eq!(parse_file_header(&[0u8; 128]), None);
+    }
+}
@@ -0,0 +1,329 @@
+use std::io::{Read, Write};
+use std::time::Duration;
+use anyhow::{format_err, Result};
+use super::*;
+
+
+impl<P: Read + Write> Modem<P> {
+    // 等待接收方发起传输，返回是否使用 CRC 校验
+    pub(crate) fn wait_start(&mut self) -> Result<bool> {
+        self.reset_retry();
+        loop {
+            match self.read_byte(Duration::from_secs(10))? {
+                Some(CRC) => return Ok(true),
+                Some(NAK) => return Ok(false),
+                Some(CAN) => {
+                    if self.is_cancelled_by_peer()? {
+                        return Err(format_err!("对方取消传输"));
+                    }
+                }
+                Some(_) => continue,
+                None => self.retry(RetryKind::Timeout, "等待接收方超时")?,
+            }
+        }
+    }
+
+    // 发送一个数据块并等待应答，data 长度须为 128 或 1024
+    pub(crate) fn send_block(&mut self, num: u8, data: &[u8], crc: bool) -> Result<()> {
+        let mut packet = Vec::with_capacity(data.len() + 5);
+        packet.push(if data.len() == 1024 { STX } else { SOH });
+        packet.push(num);
+        packet.push(!num);
+        packet.extend_from_slice(data);
+        if crc {
+            packet.extend_from_slice(&crc16(data).to_be_bytes());
+        } else {
+            packet.push(checksum(data));
+        }
+
+        self.reset_retry();
+        loop {
+            self.write_all(&packet)?;
+            match self.read_byte(Duration::from_secs(10))? {
+                Some(ACK) => return Ok(()),
+                Some(NAK) => self.retry(RetryKind::Checksum, "接收方校验失败")?,
+                Some(CAN) => {
+                    if self.is_cancelled_by_peer()? {
+                        return Err(format_err!("对方取消传输"));
+                    }
+                    self.retry(RetryKind::Framing, "无效应答")?;
+                }
+                Some(_) => {
+                    self.purge()?;
+                    self.retry(RetryKind::Framing, "无效应答")?;
+                }
+                None => self.retry(RetryKind::Timeout, "等待应答超时")?,
+            }
+        }
+    }
+
+    // 从块号 1 开始发送全部数据，返回发送的有效字节数
+    pub(crate) fn send_blocks(&mut self, data: &mut dyn Read, total: u64, block_size: usize, crc: bool) -> Result<u64> {
+        let mut buf = vec![0u8; block_size];
+        let mut num = 1u8;
+        let mut done = 0u64;
+        loop {
+            let n = read_full(data, &mut buf)?;
+            if n == 0 {
+                break;
+            }
+            // 末尾不足 128 字节时改用短块，减少填充
+            let size = if n <= 128 { 128 } else { block_size };
+            buf[n..size].fill(SUB);
+            self.send_block(num, &buf[..size], crc)?;
+            done += n as u64;
+            num = num.wrapping_add(1);
+            self.emit(ModemEvent::Progress { done, total: total.max(done) });
+            if n < block_size {
+                break;
+            }
+        }
+        Ok(done)
+    }
+
+    pub(crate) fn send_eot(&mut self) -> Result<()> {
+        self.reset_retry();
+        let mut first_nak = true;
+        loop {
+            self.write_all(&[EOT])?;
+            match self.read_byte(Duration::from_secs(10))? {
+                Some(ACK) => return Ok(()),
+                // YMODEM 接收方会先 NAK 第一个 EOT，之后的 NAK 计入重试
+                Some(NAK) if std::mem::take(&mut first_nak) => continue,
+                Some(NAK) => self.retry(RetryKind::Framing, "EOT 被拒绝")?,
+                _ => self.retry(RetryKind::Timeout, "等待 EOT 应答超时")?,
+            }
+        }
+    }
+
+    // 发起接收，返回发送方的第一个字节 (SOH/STX/EOT)
+    pub(crate) fn start_recv(&mut self, crc: bool) -> Result<u8> {
+        self.reset_retry();
+        loop {
+            self.write_all(&[if crc { CRC } else { NAK }])?;
+            match self.read_byte(Duration::from_secs(3))? {
+                Some(v @ (SOH | STX | EOT)) => return Ok(v),
+                Some(CAN) => {
+                    if self.is_cancelled_by_peer()? {
+                        return Err(format_err!("对方取消传输"));
+                    }
+                }
+                _ => self.retry(RetryKind::Timeout, "等待发送方超时")?,
+            }
+        }
+    }
+
+    // 读取块头之后的内容，校验失败返回 None
+    pub(crate) fn recv_block(&mut self, header: u8, crc: bool) -> Result<Option<(u8, Vec<u8>)>> {
+        let size = if header == STX { 1024 } else { 128 };
+        let len = size + 2 + if crc { 2 } else { 1 };
+        let mut packet = Vec::with_capacity(len);
+        while packet.len() < len {
+            match self.read_byte(Duration::from_secs(1))? {
+                Some(v) => packet.push(v),
+                None => return Ok(None),
+            }
+        }
+        let (num, data, check) = (packet[0], &packet[2..size + 2], &packet[size + 2..]);
+        if packet[1] != !num {
+            return Ok(None);
+        }
+        let valid = if crc {
+            crc16(data).to_be_bytes() == check
+        } else {
+            checksum(data) == check[0]
+        };
+        Ok(if valid { Some((num, data.to_vec())) } else { None })
+    }
+
+    // 等待下一个块头
+    pub(crate) fn next_header(&mut self) -> Result<u8> {
+        loop {
+            match self.read_byte(Duration::from_secs(10))? {
+                Some(v @ (SOH | STX | EOT)) => return Ok(v),
+                Some(CAN) => {
+                    if self.is_cancelled_by_peer()? {
+                        return Err(format_err!("对方取消传输"));
+                    }
+                }
+                Some(_) => continue,
+                None => {
+                    self.retry(RetryKind::Timeout, "等待数据超时")?;
+                    self.write_all(&[NAK])?;
+                }
+            }
+        }
+    }
+
+    // 接收数据块直到 EOT，limit 为有效长度（YMODEM），返回写入的字节数
+    pub(crate) fn recv_blocks(&mut self, out: &mut dyn Write, crc: bool, mut header: u8, limit: Option<u64>, nak_first_eot: bool) -> Result<u64> {
+        let mut expect = 1u8;
+        let mut done = 0u64;
+        let mut eot = false;
+        self.reset_retry();
+        loop {
+            match header {
+                EOT => {
+                    if nak_first_eot && !eot {
+                        eot = true;
+                        self.write_all(&[NAK])?;
+                    } else {
+                        self.write_all(&[ACK])?;
+                        return Ok(done);
+                    }
+                }
+                _ => match self.recv_block(header, crc)? {
+                    Some((num, data)) if num == expect => {
+                        let n = match limit {
+                            Some(limit) => (limit.saturating_sub(done) as usize).min(data.len()),
+                            None => data.len(),
+                        };
+                        out.write_all(&data[..n])?;
+                        done += n as u64;
+                        expect = expect.wrapping_add(1);
+                        self.write_all(&[ACK])?;
+                        self.reset_retry();
+                        self.emit(ModemEvent::Progress { done, total: limit.unwrap_or(done) });
+                    }
+                    // 上一块的 ACK 丢失，发送方重发
+                    Some((num, _)) if num == expect.wrapping_sub(1) => {
+                        self.write_all(&[ACK])?;
+                    }
+                    Some(_) => {
+                        self.abort();
+                        return Err(format_err!("块序号错误"));
+                    }
+                    None => {
+                        self.retry(RetryKind::Checksum, "数据块校验失败")?;
+                        self.purge()?;
+                        self.write_all(&[NAK])?;
+                    }
+                },
+            }
+            header = self.next_header()?;
+        }
+    }
+
+    pub fn xmodem_send(&mut self, data: &mut dyn Read, total: u64, one_k: bool) -> Result<u64> {
+        let crc = self.wait_start()?;
+        // 1K 块必须使用 CRC 校验
+        let block_size = if one_k && crc { 1024 } else { 128 };
+        let done = self.send_blocks(data, total, block_size, crc)?;
+        self.send_eot()?;
+        Ok(done)
+    }
+
+    // XMODEM 没有长度信息，末尾填充的 SUB 会原样写入
+    pub fn xmodem_recv(&mut self, out: &mut dyn Write, crc: bool) -> Result<u64> {
+        let header = self.start_recv(crc)?;
+        self.recv_blocks(out, crc, header, None, false)
+    }
+}
+
+
+#[cfg(all(test, unix))]
+mod test {
+    use std::io::Cursor;
+    use std::sync::Mutex;
+    use std::thread;
+    use super::*;
+    use super::super::test::pty_pair;
+
+    fn read_exact(port: &mut impl Read, len: usize) -> Vec<u8> {
+        let mut buf = vec![0u8; len];
+        let mut n = 0;
+        while n < len {
+            n += port.read(&mut buf[n..]).unwrap_or(0);
+        }
+        buf
+    }
+
+    fn round_trip(len: usize, crc: bool, one_k: bool) -> Vec<u8> {
+        let (a, b) = pty_pair();
+        let data = (0..len).map(|v| (v * 7) as u8).collect::<Vec<u8>>();
+        let data_clone = data.clone();
+        // 两端都结束后再关闭，避免对方读到挂断
+        let t = thread::spawn(move || {
+            let mut modem = Modem::new(a);
+            (modem.xmodem_send(&mut Cursor::new(data_clone), len as u64, one_k).unwrap(), modem.into_inner())
+        });
+        let mut out = Vec::new();
+        let mut modem = Modem::new(b);
+        modem.xmodem_recv(&mut out, crc).unwrap();
+        assert_eq!(t.join().unwrap().0, len as u64);
+        assert_eq!(&out[..len], &data[..]);
+        assert!(out[len..].iter().all(|v| *v == SUB));
+        out
+    }
+
+    #[test]
+    fn test_xmodem_checksum() {
+        assert_eq!(round_trip(300, false, false).len(), 384);
+    }
+
+    #[test]
+    fn test_xmodem_crc() {
+        assert_eq!(round_trip(256, true, false).len(), 256);
+    }
+
+    #[test]
+    fn test_xmodem_1k() {
+        // 2048 + 128（末尾短块）
+        assert_eq!(round_trip(2100, true, true).len(), 2176);
+    }
+
+    #[test]
+    fn test_xmodem_cancel() {
+        let (a, b) = pty_pair();
+        let cancel = Arc::new(AtomicBool::new(true));
+        let t = thread::spawn(move || {
+            let mut modem = Modem::new(a);
+            (modem.xmodem_send(&mut Cursor::new(vec![0u8; 10]), 10, false), modem.into_inner())
+        });
+        let mut modem = Modem::new(b);
+        modem.set_cancel(cancel);
+        assert!(modem.xmodem_recv(&mut Vec::new(), true).is_err());
+        assert!(t.join().unwrap().0.is_err());
+    }
+
+    #[test]
+    fn test_xmodem_retry_kind() {
+        let (a, mut b) = pty_pair();
+        // 接收方依次回复 NAK、无效应答、ACK
+        let t = thread::spawn(move || {
+            for reply in [NAK, b'X', ACK] {
+                assert_eq!(read_exact(&mut b, 133)[0], SOH);
+                b.write_all(&[reply]).unwrap();
+            }
+            b
+        });
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let events_clone = events.clone();
+        let mut modem = Modem::new(a);
+        modem.set_handler(move |e| {
+            if let ModemEvent::Retry { kind, .. } = e {
+                events_clone.lock().unwrap().push(kind);
+            }
+        });
+        modem.send_block(1, &[0u8; 128], true).unwrap();
+        let _b = t.join().unwrap();
+        assert_eq!(*events.lock().unwrap(), vec![RetryKind::Checksum, RetryKind::Framing]);
+    }
+
+    #[test]
+    fn test_xmodem_eot_nak() {
+        let (a, mut b) = pty_pair();
+        // 接收方一直拒绝 EOT，超过重试次数后结束
+        let t = thread::spawn(move || {
+            for _ in 0..4 {
+                assert_eq!(read_exact(&mut b, 1), [EOT]);
+                b.write_all(&[NAK]).unwrap();
+            }
+            b
+        });
+        let mut modem = Modem::new(a);
+        modem.set_max_retries(2);
+        assert!(modem.send_eot().is_err());
+        let _b = t.join().unwrap();
+    }
+}
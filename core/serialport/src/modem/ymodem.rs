@@ -0,0 +1,134 @@
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use anyhow::{format_err, Result};
+use super::*;
+
+
+impl<P: Read + Write> Modem<P> {
+    // 发送块 0，文件头或结束批次的空块
+    fn send_block0(&mut self, header: &[u8]) -> Result<()> {
+        let size = if header.len() > 128 { 1024 } else { 128 };
+        if header.len() > size {
+            return Err(format_err!("文件名过长"));
+        }
+        let mut block = vec![0u8; size];
+        block[..header.len()].copy_from_slice(header);
+        self.send_block(0, &block, true)
+    }
+
+    pub fn ymodem_send(&mut self, files: Vec<ModemFile>) -> Result<u64> {
+        let mut total = 0;
+        for mut file in files {
+            self.wait_start()?;
+            self.send_block0(&file.header())?;
+            self.emit(ModemEvent::File { name: file.name.clone(), size: file.size });
+            // 接收方确认文件头后再次发送 'C' 开始数据传输
+            self.wait_start()?;
+            total += self.send_blocks(&mut file.data, file.size, 1024, true)?;
+            self.send_eot()?;
+        }
+        self.wait_start()?;
+        self.send_block0(&[])?;
+        Ok(total)
+    }
+
+    // 接收一批文件到 dir，返回接收到的文件路径
+    pub fn ymodem_recv(&mut self, dir: &Path) -> Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+        loop {
+            let mut header = self.start_recv(true)?;
+            let block = loop {
+                if header == EOT {
+                    // 上个文件最后的 ACK 丢失
+                    self.write_all(&[ACK])?;
+                } else if let Some((0, data)) = self.recv_block(header, true)? {
+                    break data;
+                } else {
+                    self.retry(RetryKind::Checksum, "文件头校验失败")?;
+                    self.purge()?;
+                    self.write_all(&[NAK])?;
+                }
+                header = self.next_header()?;
+            };
+            self.write_all(&[ACK])?;
+
+            // 空文件名表示批次结束
+            let Some((name, size, modified)) = parse_file_header(&block) else {
+                return Ok(files);
+            };
+            self.emit(ModemEvent::File { name: name.clone(), size: size.unwrap_or(0) });
+            let (mut file, path) = create_recv_file(dir, &name)?;
+            let header = self.start_recv(true)?;
+            // 没有长度信息时与 XMODEM 相同，末尾填充原样写入
+            self.recv_blocks(&mut file, true, header, size, true)?;
+            set_recv_file_time(&file, modified);
+            files.push(path);
+        }
+    }
+}
+
+
+#[cfg(all(test, unix))]
+mod test {
+    use std::io::Cursor;
+    use std::thread;
+    use super::*;
+    use super::super::test::pty_pair;
+
+    #[test]
+    fn test_ymodem_batch() {
+        let (a, b) = pty_pair();
+        let file_a = (0..3000).map(|v| v as u8).collect::<Vec<u8>>();
+        let file_b = b"hello ymodem".to_vec();
+        let files = vec![
+            ModemFile { name: "a.bin".to_string(), size: 3000, modified: 1700000000, data: Box::new(Cursor::new(file_a.clone())) },
+            ModemFile { name: "b.txt".to_string(), size: 12, modified: 0, data: Box::new(Cursor::new(file_b.clone())) },
+        ];
+        let t = thread::spawn(move || {
+            let mut modem = Modem::new(a);
+            (modem.ymodem_send(files).unwrap(), modem.into_inner())
+        });
+
+        let dir = std::env::temp_dir().join("multi_tools_ymodem");
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut modem = Modem::new(b);
+        let recv = modem.ymodem_recv(&dir).unwrap();
+
+        assert_eq!(t.join().unwrap().0, 3012);
+        assert_eq!(recv, vec![dir.join("a.bin"), dir.join("b.txt")]);
+        assert_eq!(std::fs::read(dir.join("a.bin")).unwrap(), file_a);
+        assert_eq!(std::fs::read(dir.join("b.txt")).unwrap(), file_b);
+        let modified = std::fs::metadata(dir.join("a.bin")).unwrap().modified().unwrap();
+        assert_eq!(modified.duration_since(UNIX_EPOCH).unwrap().as_secs(), 1700000000);
+    }
+
+    #[test]
+    fn test_ymodem_no_size() {
+        let (a, b) = pty_pair();
+        let data = b"no size field".to_vec();
+        let data_clone = data.clone();
+        // 文件头省略大小字段
+        let t = thread::spawn(move || {
+            let mut modem = Modem::new(a);
+            modem.wait_start().unwrap();
+            modem.send_block0(b"c.txt\x00").unwrap();
+            modem.wait_start().unwrap();
+            modem.send_blocks(&mut Cursor::new(data_clone), 13, 1024, true).unwrap();
+            modem.send_eot().unwrap();
+            modem.wait_start().unwrap();
+            modem.send_block0(&[]).unwrap();
+            modem.into_inner()
+        });
+
+        let dir = std::env::temp_dir().join("multi_tools_ymodem_no_size");
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut modem = Modem::new(b);
+        let recv = modem.ymodem_recv(&dir).unwrap();
+        t.join().unwrap();
+
+        assert_eq!(recv, vec![dir.join("c.txt")]);
+        let file = std::fs::read(dir.join("c.txt")).unwrap();
+        assert!(file.starts_with(&data));
+        assert!(file[data.len()..].iter().all(|v| *v == SUB));
+    }
+}
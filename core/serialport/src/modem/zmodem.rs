@@ -0,0 +1,544 @@
+use std::io::{Read, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use anyhow::{format_err, Result};
+use super::*;
+
+const ZPAD: u8 = b'*';
+const ZDLE: u8 = 0x18;
+const ZBIN: u8 = b'A';
+const ZHEX: u8 = b'B';
+const ZBIN32: u8 = b'C';
+const XON: u8 = 0x11;
+const XOFF: u8 = 0x13;
+
+// 帧类型
+const ZRQINIT: u8 = 0;
+const ZRINIT: u8 = 1;
+const ZSINIT: u8 = 2;
+const ZACK: u8 = 3;
+const ZFILE: u8 = 4;
+const ZSKIP: u8 = 5;
+const ZNAK: u8 = 6;
+const ZFIN: u8 = 8;
+const ZRPOS: u8 = 9;
+const ZDATA: u8 = 10;
+const ZEOF: u8 = 11;
+const ZCHALLENGE: u8 = 14;
+
+// 数据子包结束符
+const ZCRCE: u8 = b'h';
+const ZCRCG: u8 = b'i';
+const ZCRCQ: u8 = b'j';
+const ZCRCW: u8 = b'k';
+const ZRUB0: u8 = b'l';
+const ZRUB1: u8 = b'm';
+
+// ZRINIT 能力标志 (ZF0)
+const CANFDX: u8 = 0x01;
+const CANOVIO: u8 = 0x02;
+const CANFC32: u8 = 0x20;
+// ZFILE 转换选项 (ZF0)，二进制传输
+const ZCBIN: u8 = 1;
+
+// 每个 ZDATA 帧内的子包数，最后一个子包等待 ZACK
+const WINDOW: usize = 8;
+const SUBPACKET: usize = 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Header {
+    kind: u8,
+    data: [u8; 4],
+    crc32: bool,
+}
+
+impl Header {
+    fn pos(&self) -> u64 {
+        u32::from_le_bytes(self.data) as u64
+    }
+}
+
+fn pos_data(pos: u64) -> [u8; 4] {
+    (pos as u32).to_le_bytes()
+}
+
+enum ZByte {
+    Byte(u8),
+    End(u8),
+}
+
+fn escape(data: &[u8], out: &mut Vec<u8>) {
+    for v in data {
+        match *v {
+            ZDLE | 0x10 | 0x90 | XON | 0x91 | XOFF | 0x93 => {
+                out.push(ZDLE);
+                out.push(v ^ 0x40);
+            }
+            _ => out.push(*v),
+        }
+    }
+}
+
+fn to_hex(data: &[u8], out: &mut Vec<u8>) {
+    for v in data {
+        out.extend_from_slice(format!("{:02x}", v).as_bytes());
+    }
+}
+
+impl<P: Read + Write> Modem<P> {
+    fn write_hex_header(&mut self, kind: u8, data: [u8; 4]) -> Result<()> {
+        let mut frame = vec![ZPAD, ZPAD, ZDLE, ZHEX];
+        let mut body = vec![kind];
+        body.extend_from_slice(&data);
+        to_hex(&body, &mut frame);
+        to_hex(&crc16(&body).to_be_bytes(), &mut frame);
+        frame.extend_from_slice(b"\r\x8a");
+        if kind != ZFIN && kind != ZACK {
+            frame.push(XON);
+        }
+        self.write_all(&frame)
+    }
+
+    fn write_bin_header(&mut self, kind: u8, data: [u8; 4], crc32: bool) -> Result<()> {
+        let mut frame = vec![ZPAD, ZDLE, if crc32 { ZBIN32 } else { ZBIN }];
+        let mut body = vec![kind];
+        body.extend_from_slice(&data);
+        escape(&body, &mut frame);
+        if crc32 {
+            escape(&super::crc32(&body).to_le_bytes(), &mut frame);
+        } else {
+            escape(&crc16(&body).to_be_bytes(), &mut frame);
+        }
+        self.write_all(&frame)
+    }
+
+    fn write_subpacket(&mut self, data: &[u8], end: u8, crc32: bool) -> Result<()> {
+        let mut frame = Vec::with_capacity(data.len() * 2 + 16);
+        escape(data, &mut frame);
+        frame.push(ZDLE);
+        frame.push(end);
+        let mut body = data.to_vec();
+        body.push(end);
+        if crc32 {
+            escape(&super::crc32(&body).to_le_bytes(), &mut frame);
+        } else {
+            escape(&crc16(&body).to_be_bytes(), &mut frame);
+        }
+        if end == ZCRCW {
+            frame.push(XON);
+        }
+        self.write_all(&frame)
+    }
+
+    fn write_rinit(&mut self) -> Result<()> {
+        self.write_hex_header(ZRINIT, [0, 0, 0, CANFDX | CANOVIO | CANFC32])
+    }
+
+    // 读取一个去转义后的字节，超时或非法转义返回 None
+    fn read_zbyte(&mut self, timeout: Duration) -> Result<Option<ZByte>> {
+        let mut can = 0;
+        loop {
+            let Some(v) = self.read_byte(timeout)? else { return Ok(None) };
+            match v {
+                XON | XOFF | 0x91 | 0x93 => continue,
+                ZDLE => {
+                    can += 1;
+                    // 连续 5 个 CAN 表示对方取消
+                    if can >= 5 {
+                        return Err(format_err!("对方取消传输"));
+                    }
+                    continue;
+                }
+                _ if can == 0 => return Ok(Some(ZByte::Byte(v))),
+                ZCRCE | ZCRCG | ZCRCQ | ZCRCW => return Ok(Some(ZByte::End(v))),
+                ZRUB0 => return Ok(Some(ZByte::Byte(0x7F))),
+                ZRUB1 => return Ok(Some(ZByte::Byte(0xFF))),
+                _ if v & 0x60 == 0x40 => return Ok(Some(ZByte::Byte(v ^ 0x40))),
+                _ => return Ok(None),
+            }
+        }
+    }
+
+    fn read_zbytes(&mut self, n: usize) -> Result<Option<Vec<u8>>> {
+        let mut v = Vec::with_capacity(n);
+        while v.len() < n {
+            match self.read_zbyte(Duration::from_secs(1))? {
+                Some(ZByte::Byte(x)) => v.push(x),
+                _ => return Ok(None),
+            }
+        }
+        Ok(Some(v))
+    }
+
+    fn read_hex_byte(&mut self) -> Result<Option<u8>> {
+        let mut s = String::new();
+        for _ in 0..2 {
+            match self.read_byte(Duration::from_secs(1))? {
+                Some(v) => s.push(v as char),
+                None => return Ok(None),
+            }
+        }
+        Ok(u8::from_str_radix(&s, 16).ok())
+    }
+
+    // 跳过无关数据，读取下一个校验正确的帧头
+    fn read_header(&mut self, timeout: Duration) -> Result<Option<Header>> {
+        let deadline = Instant::now() + timeout;
+        let mut can = 0;
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            let Some(v) = self.read_byte(left)? else { return Ok(None) };
+            if v == CAN {
+                can += 1;
+                if can >= 5 {
+                    return Err(format_err!("对方取消传输"));
+                }
+                continue;
+            }
+            can = 0;
+            if v != ZPAD {
+                continue;
+            }
+            // 帧头格式 ZPAD [ZPAD] ZDLE 类型
+            let mut v = self.read_byte(Duration::from_secs(1))?;
+            while v == Some(ZPAD) {
+                v = self.read_byte(Duration::from_secs(1))?;
+            }
+            if v != Some(ZDLE) {
+                continue;
+            }
+            let header = match self.read_byte(Duration::from_secs(1))? {
+                Some(ZHEX) => self.read_hex_header()?,
+                Some(ZBIN) => self.read_bin_header(false)?,
+                Some(ZBIN32) => self.read_bin_header(true)?,
+                _ => None,
+            };
+            if header.is_some() {
+                return Ok(header);
+            }
+        }
+    }
+
+    fn read_hex_header(&mut self) -> Result<Option<Header>> {
+        let mut body = Vec::with_capacity(7);
+        for _ in 0..7 {
+            match self.read_hex_byte()? {
+                Some(v) => body.push(v),
+                None => return Ok(None),
+            }
+        }
+        if crc16(&body[..5]).to_be_bytes() != body[5..] {
+            return Ok(None);
+        }
+        // 丢弃帧尾的 CR LF
+        for _ in 0..2 {
+            match self.read_byte(Duration::from_millis(500))? {
+                Some(b'\r' | b'\n' | 0x8A) | None => {}
+                Some(v) => {
+                    self.unread_byte(v);
+                    break;
+                }
+            }
+        }
+        Ok(Some(Header { kind: body[0], data: [body[1], body[2], body[3], body[4]], crc32: false }))
+    }
+
+    fn read_bin_header(&mut self, crc32: bool) -> Result<Option<Header>> {
+        let Some(body) = self.read_zbytes(if crc32 { 9 } else { 7 })? else { return Ok(None) };
+        let valid = if crc32 {
+            super::crc32(&body[..5]).to_le_bytes() == body[5..]
+        } else {
+            crc16(&body[..5]).to_be_bytes() == body[5..]
+        };
+        Ok(if valid { Some(Header { kind: body[0], data: [body[1], body[2], body[3], body[4]], crc32 }) } else { None })
+    }
+
+    // 读取一个数据子包，返回 (数据, 结束符)，校验失败返回 None
+    fn read_subpacket(&mut self, crc32: bool) -> Result<Option<(Vec<u8>, u8)>> {
+        let mut data = Vec::with_capacity(SUBPACKET);
+        let end = loop {
+            match self.read_zbyte(Duration::from_secs(2))? {
+                Some(ZByte::Byte(v)) => {
+                    if data.len() > SUBPACKET * 8 {
+                        return Ok(None);
+                    }
+                    data.push(v);
+                }
+                Some(ZByte::End(v)) => break v,
+                None => return Ok(None),
+            }
+        };
+        let Some(check) = self.read_zbytes(if crc32 { 4 } else { 2 })? else { return Ok(None) };
+        data.push(end);
+        let valid = if crc32 {
+            super::crc32(&data).to_le_bytes() == check[..]
+        } else {
+            crc16(&data).to_be_bytes() == check[..]
+        };
+        data.pop();
+        Ok(if valid { Some((data, end)) } else { None })
+    }
+
+    // 等待 ZRINIT，返回接收方能力标志
+    fn zwait_rinit(&mut self) -> Result<u8> {
+        self.reset_retry();
+        loop {
+            match self.read_header(Duration::from_secs(10))? {
+                Some(h) if h.kind == ZRINIT => return Ok(h.data[3]),
+                Some(h) if h.kind == ZCHALLENGE => self.write_hex_header(ZACK, h.data)?,
+                Some(_) => continue,
+                None => {
+                    self.retry(RetryKind::Timeout, "等待 ZRINIT 超时")?;
+                    self.write_hex_header(ZRQINIT, [0; 4])?;
+                }
+            }
+        }
+    }
+
+    // 从 pos 开始发送文件数据直到结尾，返回结束位置；接收方要求重传时返回 Err(重传位置)
+    fn zsend_data(&mut self, file: &mut ModemFile, mut pos: u64, crc32: bool) -> Result<std::result::Result<u64, u64>> {
+        let mut buf = vec![0u8; SUBPACKET];
+        file.data.seek(SeekFrom::Start(pos))?;
+        loop {
+            let acked = pos;
+            self.write_bin_header(ZDATA, pos_data(pos), crc32)?;
+            let mut eof = false;
+            for i in 0..WINDOW {
+                let n = read_full(&mut file.data, &mut buf)?;
+                eof = n < SUBPACKET;
+                let end = if eof || i == WINDOW - 1 { ZCRCW } else { ZCRCG };
+                self.write_subpacket(&buf[..n], end, crc32)?;
+                pos += n as u64;
+                self.emit(ModemEvent::Progress { done: pos, total: file.size.max(pos) });
+                if eof {
+                    break;
+                }
+            }
+            loop {
+                match self.read_header(Duration::from_secs(10))? {
+                    Some(h) if h.kind == ZACK => {
+                        self.reset_retry();
+                        break;
+                    }
+                    Some(h) if h.kind == ZRPOS => {
+                        self.retry(RetryKind::Checksum, "接收方要求重传")?;
+                        return Ok(Err(h.pos()));
+                    }
+                    Some(_) => continue,
+                    None => {
+                        self.retry(RetryKind::Timeout, "等待 ZACK 超时")?;
+                        return Ok(Err(acked));
+                    }
+                }
+            }
+            if eof {
+                return Ok(Ok(pos));
+            }
+        }
+    }
+
+    // 发送单个文件，接收方跳过时返回 0
+    fn zsend_file(&mut self, file: &mut ModemFile, crc32: bool) -> Result<u64> {
+        let mut pos = loop {
+            self.write_bin_header(ZFILE, [0, 0, 0, ZCBIN], crc32)?;
+            self.write_subpacket(&file.header(), ZCRCW, crc32)?;
+            // 开始阶段接收方可能连续发出多个 ZRINIT，忽略即可
+            let h = loop {
+                match self.read_header(Duration::from_secs(10))? {
+                    Some(h) if h.kind == ZRINIT => continue,
+                    h => break h,
+                }
+            };
+            match h {
+                Some(h) if h.kind == ZRPOS => break h.pos(),
+                Some(h) if h.kind == ZSKIP => return Ok(0),
+                _ => self.retry(RetryKind::Timeout, "等待 ZRPOS 超时")?,
+            }
+        };
+        self.emit(ModemEvent::File { name: file.name.clone(), size: file.size });
+
+        self.reset_retry();
+        loop {
+            match self.zsend_data(file, pos, crc32)? {
+                Ok(end) => {
+                    self.write_hex_header(ZEOF, pos_data(end))?;
+                    match self.read_header(Duration::from_secs(10))? {
+                        Some(h) if h.kind == ZRINIT => return Ok(end),
+                        Some(h) if h.kind == ZRPOS => pos = h.pos(),
+                        _ => {
+                            self.retry(RetryKind::Timeout, "等待 ZEOF 应答超时")?;
+                            pos = end;
+                        }
+                    }
+                }
+                Err(resume) => pos = resume,
+            }
+        }
+    }
+
+    pub fn zmodem_send(&mut self, files: Vec<ModemFile>) -> Result<u64> {
+        self.write_all(b"rz\r")?;
+        self.write_hex_header(ZRQINIT, [0; 4])?;
+        let crc32 = self.zwait_rinit()? & CANFC32 != 0;
+
+        let mut total = 0;
+        for mut file in files {
+            total += self.zsend_file(&mut file, crc32)?;
+        }
+
+        self.reset_retry();
+        loop {
+            self.write_hex_header(ZFIN, [0; 4])?;
+            match self.read_header(Duration::from_secs(10))? {
+                Some(h) if h.kind == ZFIN => break,
+                _ => self.retry(RetryKind::Timeout, "等待 ZFIN 超时")?,
+            }
+        }
+        self.write_all(b"OO")?;
+        Ok(total)
+    }
+
+    // 接收单个文件的数据直到 ZEOF
+    fn zrecv_file(&mut self, out: &mut dyn Write, size: u64) -> Result<u64> {
+        let mut pos = 0u64;
+        self.reset_retry();
+        self.write_hex_header(ZRPOS, pos_data(pos))?;
+        loop {
+            let Some(h) = self.read_header(Duration::from_secs(10))? else {
+                self.retry(RetryKind::Timeout, "等待数据超时")?;
+                self.write_hex_header(ZRPOS, pos_data(pos))?;
+                continue;
+            };
+            match h.kind {
+                ZDATA => {
+                    if h.pos() != pos {
+                        self.purge()?;
+                        self.write_hex_header(ZRPOS, pos_data(pos))?;
+                        continue;
+                    }
+                    loop {
+                        let Some((data, end)) = self.read_subpacket(h.crc32)? else {
+                            self.retry(RetryKind::Checksum, "数据子包校验失败")?;
+                            self.purge()?;
+                            self.write_hex_header(ZRPOS, pos_data(pos))?;
+                            break;
+                        };
+                        out.write_all(&data)?;
+                        pos += data.len() as u64;
+                        self.emit(ModemEvent::Progress { done: pos, total: size.max(pos) });
+                        match end {
+                            ZCRCW => {
+                                self.reset_retry();
+                                self.write_hex_header(ZACK, pos_data(pos))?;
+                                break;
+                            }
+                            ZCRCQ => self.write_hex_header(ZACK, pos_data(pos))?,
+                            ZCRCE => break,
+                            _ => {}
+                        }
+                    }
+                }
+                // 位置不符的 ZEOF 忽略
+                ZEOF if h.pos() == pos => {
+                    self.write_rinit()?;
+                    return Ok(pos);
+                }
+                // 发送方没有收到 ZRPOS
+                ZFILE => {
+                    self.read_subpacket(h.crc32)?;
+                    self.write_hex_header(ZRPOS, pos_data(pos))?;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // 接收文件到 dir，返回接收到的文件路径
+    pub fn zmodem_recv(&mut self, dir: &Path) -> Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+        self.reset_retry();
+        self.write_rinit()?;
+        loop {
+            let Some(h) = self.read_header(Duration::from_secs(10))? else {
+                self.retry(RetryKind::Timeout, "等待发送方超时")?;
+                self.write_rinit()?;
+                continue;
+            };
+            match h.kind {
+                ZRQINIT => self.write_rinit()?,
+                ZSINIT => {
+                    self.read_subpacket(h.crc32)?;
+                    self.write_hex_header(ZACK, [0; 4])?;
+                }
+                ZFILE => {
+                    let Some((info, _)) = self.read_subpacket(h.crc32)? else {
+                        self.retry(RetryKind::Checksum, "文件头校验失败")?;
+                        self.write_hex_header(ZNAK, [0; 4])?;
+                        continue;
+                    };
+                    let Some((name, size, modified)) = parse_file_header(&info) else {
+                        self.write_hex_header(ZSKIP, [0; 4])?;
+                        continue;
+                    };
+                    self.emit(ModemEvent::File { name: name.clone(), size: size.unwrap_or(0) });
+                    let (mut file, path) = create_recv_file(dir, &name)?;
+                    self.zrecv_file(&mut file, size.unwrap_or(0))?;
+                    set_recv_file_time(&file, modified);
+                    files.push(path);
+                    self.reset_retry();
+                }
+                ZFIN => {
+                    self.write_hex_header(ZFIN, [0; 4])?;
+                    // 读掉发送方的 "OO"，此时发送方可能已关闭，忽略错误
+                    for _ in 0..2 {
+                        self.read_byte(Duration::from_millis(500)).unwrap_or_default();
+                    }
+                    return Ok(files);
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+
+#[cfg(all(test, unix))]
+mod test {
+    use std::io::Cursor;
+    use std::thread;
+    use super::*;
+    use super::super::test::pty_pair;
+
+    #[test]
+    fn test_escape() {
+        let mut out = Vec::new();
+        escape(&[0x00, ZDLE, XON, 0x93, 0x41], &mut out);
+        assert_eq!(out, vec![0x00, ZDLE, ZDLE ^ 0x40, ZDLE, XON ^ 0x40, ZDLE, 0x93 ^ 0x40, 0x41]);
+    }
+
+    #[test]
+    fn test_zmodem_files() {
+        let (a, b) = pty_pair();
+        // 覆盖需要转义的字节
+        let file_a = (0..10000).map(|v| (v % 256) as u8).collect::<Vec<u8>>();
+        let file_b = b"hello zmodem".to_vec();
+        let files = vec![
+            ModemFile { name: "a.bin".to_string(), size: 10000, modified: 1700000000, data: Box::new(Cursor::new(file_a.clone())) },
+            ModemFile { name: "b.txt".to_string(), size: 12, modified: 0, data: Box::new(Cursor::new(file_b.clone())) },
+        ];
+        let t = thread::spawn(move || {
+            let mut modem = Modem::new(a);
+            (modem.zmodem_send(files).unwrap(), modem.into_inner())
+        });
+
+        let dir = std::env::temp_dir().join("multi_tools_zmodem");
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut modem = Modem::new(b);
+        let recv = modem.zmodem_recv(&dir).unwrap();
+
+        assert_eq!(t.join().unwrap().0, 10012);
+        assert_eq!(recv, vec![dir.join("a.bin"), dir.join("b.txt")]);
+        assert_eq!(std::fs::read(dir.join("a.bin")).unwrap(), file_a);
+        assert_eq!(std::fs::read(dir.join("b.txt")).unwrap(), file_b);
+    }
+}
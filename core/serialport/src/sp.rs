@@ -73,6 +73,12 @@ impl Serial {
             .try_clone()?;
         Ok((port, connected, buffer_size))
     }
+
+    // 克隆一个独立的串口句柄，用于文件发送等需要同步写入的场景
+    pub fn clone_port(&self) -> Result<Box<dyn SerialPort>> {
+        let (port, _, _) = self.get_port_info()?;
+        Ok(port)
+    }
 }
 
 impl Serial {
@@ -0,0 +1,181 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Local};
+use encoding_rs::Encoding;
+use serde::{Deserialize, Serialize};
+use crate::codec::StreamDecoder;
+use crate::search::{Matcher, SearchQuery, StreamMatcher};
+
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    #[default]
+    Warning,
+    Error,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Info => "信息",
+            Severity::Warning => "警告",
+            Severity::Error => "错误",
+        }
+    }
+}
+
+// 告警规则，cooldown 毫秒内重复匹配不再告警
+#[derive(Clone, Debug, Deserialize)]
+pub struct AlertRule {
+    pub name: String,
+    pub query: SearchQuery,
+    #[serde(default)]
+    pub severity: Severity,
+    #[serde(default)]
+    pub cooldown: u64,
+    // 是否弹出系统通知
+    #[serde(default)]
+    pub notify: bool,
+}
+
+// 告警记录，通过 alert_{id} 事件发往前端并保存在历史中
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct AlertRecord {
+    pub index: usize,
+    pub name: String,
+    pub severity: Severity,
+    // 匹配到的内容
+    pub text: String,
+    pub time: String,
+    #[serde(skip)]
+    pub notify: bool,
+}
+
+// 告警历史上限，超出后丢弃最早的记录
+const HISTORY_LIMIT: usize = 1000;
+
+#[derive(Debug)]
+struct Alert {
+    rule: AlertRule,
+    matcher: StreamMatcher,
+    last: Option<Instant>,
+}
+
+// 单个会话的全部告警规则与告警历史
+#[derive(Debug)]
+pub struct AlertSet {
+    alerts: Vec<Alert>,
+    decoder: StreamDecoder,
+    history: VecDeque<AlertRecord>,
+}
+
+impl AlertSet {
+    // 文本按 code 解码后匹配
+    pub fn new(rules: Vec<(AlertRule, Matcher)>, code: &'static Encoding) -> Self {
+        Self {
+            alerts: rules.into_iter().map(|(rule, matcher)| Alert {
+                rule,
+                matcher: StreamMatcher::new(matcher),
+                last: None,
+            }).collect(),
+            decoder: StreamDecoder::new(code),
+            history: VecDeque::new(),
+        }
+    }
+
+    // 替换规则，保留告警历史
+    pub fn set_rules(&mut self, rules: Vec<(AlertRule, Matcher)>, code: &'static Encoding) {
+        let history = std::mem::take(&mut self.history);
+        *self = Self::new(rules, code);
+        self.history = history;
+    }
+
+    pub fn history(&self) -> Vec<AlertRecord> {
+        self.history.iter().cloned().collect()
+    }
+
+    pub fn clear_history(&mut self) {
+        self.history.clear();
+    }
+
+    // 输入接收数据，返回本次产生的告警，冷却期内的匹配被忽略
+    pub fn feed(&mut self, data: &[u8], now: Instant, time: DateTime<Local>) -> Vec<AlertRecord> {
+        let text = self.decoder.decode(data);
+        let mut records = Vec::new();
+        for (index, a) in self.alerts.iter_mut().enumerate() {
+            a.matcher.push(&text, data);
+            while let Some(v) = a.matcher.next_match() {
+                let cooldown = Duration::from_millis(a.rule.cooldown);
+                if a.last.is_some_and(|last| now.duration_since(last) < cooldown) {
+                    continue;
+                }
+                a.last = Some(now);
+                records.push(AlertRecord {
+                    index,
+                    name: a.rule.name.clone(),
+                    severity: a.rule.severity,
+                    text: v,
+                    time: time.format("%Y-%m-%d %H:%M:%S%.6f").to_string(),
+                    notify: a.rule.notify,
+                });
+            }
+        }
+        for record in &records {
+            if self.history.len() >= HISTORY_LIMIT {
+                self.history.pop_front();
+            }
+            self.history.push_back(record.clone());
+        }
+        records
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::search::SearchKind;
+
+    fn rule(name: &str, kind: SearchKind, pattern: &str, cooldown: u64) -> (AlertRule, Matcher) {
+        let query = SearchQuery { kind, pattern: pattern.to_string(), ignore_case: false };
+        let matcher = Matcher::new(&query).unwrap();
+        (AlertRule { name: name.to_string(), query, severity: Severity::Error, cooldown, notify: false }, matcher)
+    }
+
+    #[test]
+    fn test_alert_feed() {
+        let mut set = AlertSet::new(vec![
+            rule("fault", SearchKind::Text, "HardFault", 1000),
+            rule("code", SearchKind::Regex, r"E\d{3}", 0),
+        ], encoding_rs::UTF_8);
+        let now = Instant::now();
+        let time = Local::now();
+
+        let records = set.feed(b"Hard", now, time);
+        assert!(records.is_empty());
+        let records = set.feed(b"Fault E101 E102", now, time);
+        assert_eq!(records.iter().map(|v| (v.name.as_str(), v.text.as_str())).collect::<Vec<_>>(),
+                   vec![("fault", "HardFault"), ("code", "E101"), ("code", "E102")]);
+
+        // 冷却期内不再告警
+        assert!(set.feed(b"HardFault", now + Duration::from_millis(500), time).is_empty());
+        assert_eq!(set.feed(b"HardFault", now + Duration::from_millis(1500), time).len(), 1);
+        assert_eq!(set.history().len(), 4);
+
+        set.set_rules(Vec::new(), encoding_rs::UTF_8);
+        assert_eq!(set.history().len(), 4);
+        set.clear_history();
+        assert!(set.history().is_empty());
+    }
+
+    #[test]
+    fn test_alert_empty_match() {
+        let mut set = AlertSet::new(vec![rule("any", SearchKind::Regex, ".*", 0)], encoding_rs::UTF_8);
+        let (now, time) = (Instant::now(), Local::now());
+        assert!(set.feed(b"", now, time).is_empty());
+        let records = set.feed(b"ok\n", now, time);
+        assert_eq!(records.iter().map(|v| v.text.as_str()).collect::<Vec<_>>(), vec!["ok"]);
+    }
+}
@@ -0,0 +1,201 @@
+use encoding_rs::*;
+use serde::{Deserialize, Serialize};
+
+
+// 支持的收发编码，顺序即前端下拉框顺序
+pub static ENCODINGS: [&Encoding; 39] = [
+    UTF_8, GBK, GB18030, BIG5, SHIFT_JIS, EUC_JP, ISO_2022_JP, EUC_KR, UTF_16LE, UTF_16BE,
+    WINDOWS_1252, WINDOWS_1250, WINDOWS_1251, WINDOWS_1253, WINDOWS_1254, WINDOWS_1255, WINDOWS_1256, WINDOWS_1257, WINDOWS_1258, WINDOWS_874,
+    ISO_8859_2, ISO_8859_3, ISO_8859_4, ISO_8859_5, ISO_8859_6, ISO_8859_7, ISO_8859_8, ISO_8859_8_I, ISO_8859_10, ISO_8859_13,
+    ISO_8859_14, ISO_8859_15, ISO_8859_16, KOI8_R, KOI8_U, IBM866, MACINTOSH, X_MAC_CYRILLIC, X_USER_DEFINED,
+];
+
+// 按标签查找编码，如 "utf-8"、"gbk"、"shift_jis"、"utf-16le"
+pub fn encoding_for_label(label: &str) -> Option<&'static Encoding> {
+    let encoding = Encoding::for_label(label.trim().as_bytes())?;
+    // replacement 编码只用于兼容旧标签，不能用于收发
+    if encoding == REPLACEMENT { None } else { Some(encoding) }
+}
+
+// 字节转为 "AA BB" 形式的十六进制文本
+pub fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|v| format!("{:02X}", v)).collect::<Vec<String>>().join(" ")
+}
+
+pub fn encoding_names() -> Vec<&'static str> {
+    ENCODINGS.iter().map(|v| v.name()).collect()
+}
+
+
+// 流式解码器，跨次读取保留不完整的多字节序列
+// 无法解码的字节显示为 \xFF 形式的转义标记
+#[derive(Debug)]
+pub struct StreamDecoder {
+    decoder: Decoder,
+    // 最近输入的末尾字节，错误序列可能跨越上一次的输入
+    tail: Vec<u8>,
+}
+
+impl StreamDecoder {
+    pub fn new(encoding: &'static Encoding) -> Self {
+        Self {
+            decoder: encoding.new_decoder_without_bom_handling(),
+            tail: Vec::new(),
+        }
+    }
+
+    pub fn encoding(&self) -> &'static Encoding {
+        self.decoder.encoding()
+    }
+
+    pub fn decode(&mut self, bytes: &[u8]) -> String {
+        let mut out = String::new();
+        let mut read = 0;
+        loop {
+            let need = self.decoder.max_utf8_buffer_length_without_replacement(bytes.len() - read).unwrap_or(bytes.len() * 3 + 16);
+            out.reserve(need);
+            let (result, r) = self.decoder.decode_to_string_without_replacement(&bytes[read..], &mut out, false);
+            read += r;
+            match result {
+                DecoderResult::InputEmpty => break,
+                DecoderResult::OutputFull => continue,
+                DecoderResult::Malformed(bad, after) => {
+                    // 出错字节位于本次已读部分之前 after 个字节处，不足时从上次输入末尾补齐
+                    let end = read - after as usize;
+                    let bad = bad as usize;
+                    let mut seq = Vec::with_capacity(bad);
+                    if end < bad {
+                        let need = bad - end;
+                        seq.extend_from_slice(&self.tail[self.tail.len().saturating_sub(need)..]);
+                        seq.extend_from_slice(&bytes[..end]);
+                    } else {
+                        seq.extend_from_slice(&bytes[end - bad..end]);
+                    }
+                    for v in seq {
+                        out.push_str(&format!("\\x{v:02X}"));
+                    }
+                }
+            }
+        }
+        if bytes.len() >= TAIL_LEN {
+            self.tail.clear();
+            self.tail.extend_from_slice(&bytes[bytes.len() - TAIL_LEN..]);
+        } else {
+            self.tail.extend_from_slice(bytes);
+            let over = self.tail.len().saturating_sub(TAIL_LEN);
+            self.tail.drain(..over);
+        }
+        out
+    }
+}
+
+// 各编码单个错误序列最长不超过 8 字节
+const TAIL_LEN: usize = 8;
+
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Newline {
+    #[default]
+    Lf,
+    Cr,
+    CrLf,
+}
+
+impl Newline {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Newline::Lf => "\n",
+            Newline::Cr => "\r",
+            Newline::CrLf => "\r\n",
+        }
+    }
+}
+
+// 流式统一换行符，\n、\r\n 与单独的 \r 都替换为指定换行符
+// 跨次输入的 \r\n 同样只算一个换行
+#[derive(Debug)]
+pub struct NewlineNormalizer {
+    // None 时原样输出
+    newline: Option<Newline>,
+    // 上次输入以 \r 结尾
+    last_cr: bool,
+}
+
+impl NewlineNormalizer {
+    pub fn new(newline: Option<Newline>) -> Self {
+        Self { newline, last_cr: false }
+    }
+
+    pub fn normalize(&mut self, text: String) -> String {
+        let Some(newline) = self.newline else {
+            return text;
+        };
+        let mut out = String::with_capacity(text.len());
+        for c in text.chars() {
+            match c {
+                '\r' => out.push_str(newline.as_str()),
+                '\n' if self.last_cr => {}
+                '\n' => out.push_str(newline.as_str()),
+                _ => out.push(c),
+            }
+            self.last_cr = c == '\r';
+        }
+        out
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_encoding_for_label() {
+        assert_eq!(encoding_for_label("latin1"), Some(WINDOWS_1252));
+        assert_eq!(encoding_for_label(" Shift_JIS "), Some(SHIFT_JIS));
+        assert!(encoding_for_label("iso-2022-kr").is_none());
+        assert!(encoding_for_label("nope").is_none());
+        // 列表中的名称都能重新查到自身
+        for v in ENCODINGS {
+            assert_eq!(encoding_for_label(v.name()), Some(v));
+        }
+    }
+
+    #[test]
+    fn test_stream_decoder() {
+        // "啊中" 在任意位置拆分都能完整解码
+        let bytes = "啊中".as_bytes();
+        for i in 0..bytes.len() {
+            let mut decoder = StreamDecoder::new(UTF_8);
+            let text = decoder.decode(&bytes[..i]) + &decoder.decode(&bytes[i..]);
+            assert_eq!(text, "啊中");
+        }
+        let mut decoder = StreamDecoder::new(GBK);
+        assert_eq!(decoder.decode(&[0x41, 0xB0]), "A");
+        assert_eq!(decoder.decode(&[0xA1, 0x42]), "啊B");
+    }
+
+    #[test]
+    fn test_newline_normalizer() {
+        let mut normalizer = NewlineNormalizer::new(Some(Newline::Lf));
+        assert_eq!(normalizer.normalize("a\rb\r\nc\r".to_string()), "a\nb\nc\n");
+        // 上次末尾的 \r 与本次开头的 \n 为同一个换行
+        assert_eq!(normalizer.normalize("\nd\n\n".to_string()), "d\n\n");
+        let mut normalizer = NewlineNormalizer::new(Some(Newline::CrLf));
+        assert_eq!(normalizer.normalize("a\nb\r".to_string()), "a\r\nb\r\n");
+        assert_eq!(NewlineNormalizer::new(None).normalize("a\r".to_string()), "a\r");
+    }
+
+    #[test]
+    fn test_stream_decoder_malformed() {
+        let mut decoder = StreamDecoder::new(UTF_8);
+        assert_eq!(decoder.decode(&[0x41, 0xFF, 0x42]), r"A\xFFB");
+        // 不完整序列跨越两次输入后才被判定为错误
+        assert_eq!(decoder.decode(&[0xE5, 0x95]), "");
+        assert_eq!(decoder.decode(&[0x41]), r"\xE5\x95A");
+        let mut decoder = StreamDecoder::new(UTF_16LE);
+        assert_eq!(decoder.decode(&[0x41, 0x00, 0x00]), "A");
+        assert_eq!(decoder.decode(&[0xDC, 0x42, 0x00]), r"\x00\xDCB");
+    }
+}
@@ -1,3 +1,7 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
 use std::sync::mpsc::{channel, TryRecvError};
 use std::thread;
 use std::time::Duration;
@@ -5,8 +9,21 @@ use anyhow::{Context, Result};
 use tauri::{ Manager };
 use multi_tools_serialport::sp::Serial;
 use multi_tools_serialport::sp_list::{list_available_ports, SerialInfo};
-use serde_json::{json, Value};
-use crate::manage::{MsgCode, MsgHandle, MsgHandles, SendHandles, Serials};
+use serde_json::{json, Map, Value};
+use multi_tools_serialport::modem::{Modem, ModemEvent, ModemFile, ModemProtocol, RetryKind};
+use crate::manage::{Captures, Coalescer, EmitOption, HistoryLimit, HistoryPage, HistoryStats, MsgCode, MsgHandle, MsgHandles, Loggers, LineOption, NewlineMode, NewlineOption, Plotters, RecvTaps, Replays, RowFormat, SendHandles, TimeFormat, Serials, Terminals, Transfers, Triggers, Alerts};
+use crate::logger::{Direction, LogFiles, LogOption, SessionLogger};
+use crate::pcapng::{PcapngWriter, LINKTYPE_USER0};
+use crate::plot::{PlotOption, PlotPoint, Plotter, write_csv};
+use crate::replay::{read_capture, ReplayOption};
+use crate::search::{DisplayFilter, Matcher, SearchHit, SearchQuery};
+use encoding_rs::Encoding;
+use crate::alert::{AlertRecord, AlertRule, AlertSet};
+use crate::codec::{encoding_for_label, encoding_names};
+use crate::parse::{append_newline, encode_segments, parse_escape, parse_hex, translate_newlines, ParseError, Segment};
+use crate::terminal::{encode_key, KeyInput, Terminal, TerminalOption, TerminalSnapshot};
+use crate::transfer::{SendFileOption, TapPort, TransferProgress, TransferState};
+use crate::trigger::{TriggerFire, TriggerRule, TriggerSet};
 
 macro_rules! catch_error_to_string {
     ($func:ident, $( $x:expr ),*) => {
@@ -22,34 +39,190 @@ macro_rules! catch_error_to_string {
 
 // todo 将删除和断开分离
 
-fn update_msg(app_handle: &tauri::AppHandle, id: &String, msg_send: Option<&Vec<u8>>) {
+fn update_msg(app_handle: &tauri::AppHandle, id: &String) {
     let msg_handles = app_handle.state::<MsgHandles>();
 
+    // 只发送新增的记录，reset 为 true 时前端整体替换
     let msg = match msg_handles.0.lock().unwrap().get_mut(id)  {
         None => {
             json!({
                 "recv_count": 0,
                 "send_count": 0,
-                "msg": "获取串口数据失败"
+                "seq": 0,
+                "reset": true,
+                "trim": 0,
+                "records": [],
+                "error": "获取串口数据失败"
             })
         }
         Some(x) => {
-            if let Some(m) = msg_send {
-                x.add_send_count(m);
-            }
-            let recv_count = x.recv_count;
-            let send_count = x.send_count;
-            let msg_ = x.recv_buffer_to_string();
+            let update = x.take_update();
             json!({
-                "recv_count": recv_count,
-                "send_count": send_count,
-                "msg": msg_
+                "recv_count": x.recv_count,
+                "send_count": x.send_count,
+                "dropped": x.history_stats().dropped_bytes,
+                "seq": x.seq(),
+                "reset": update.reset,
+                "trim": update.trim,
+                "records": update.records
             })
         }
     };
     app_handle.emit_all(&format!("recv_{id}"), msg).unwrap_or_default();
 }
 
+// 写入会话记录，失败时停止记录并通知前端
+fn log_io(app_handle: &tauri::AppHandle, id: &str, dir: Direction, data: &[u8]) {
+    let loggers = app_handle.state::<Loggers>();
+    let mut loggers = loggers.0.lock().unwrap();
+    if let Some(logger) = loggers.get_mut(id) {
+        if let Err(e) = logger.write(chrono::Local::now(), dir, data) {
+            loggers.remove(id);
+            app_handle.emit_all(&format!("log_error_{id}"), e.to_string()).unwrap_or_default();
+        }
+    }
+}
+
+// 数据写入串口后调用，记录并更新发送计数，前端事件由接收线程合并发送
+fn on_send(app_handle: &tauri::AppHandle, id: &String, msg: &[u8]) {
+    log_io(app_handle, id, Direction::Tx, msg);
+    if let Some(x) = app_handle.state::<MsgHandles>().0.lock().unwrap().get_mut(id) {
+        x.add_send(msg);
+    }
+    capture_records(app_handle, id);
+}
+
+// 文件与协议传输的分块写入，连续的块合并为一条记录
+fn on_send_chunk(app_handle: &tauri::AppHandle, id: &String, msg: &[u8]) {
+    log_io(app_handle, id, Direction::Tx, msg);
+    if let Some(x) = app_handle.state::<MsgHandles>().0.lock().unwrap().get_mut(id) {
+        x.add_send_chunk(msg);
+    }
+    capture_records(app_handle, id);
+}
+
+// 新增的收发记录写入实时抓包，按行接收时一行为一个数据包
+// 写入失败时停止抓包并通过 capture_error_{id} 通知前端
+// 记录在 MsgHandles 锁内取出，写文件时只持有 Captures 锁，避免慢速磁盘阻塞收发
+fn capture_records(app_handle: &tauri::AppHandle, id: &str) {
+    let captures = app_handle.state::<Captures>();
+    let Some(next) = captures.0.lock().unwrap().get(id).map(|v| v.1) else {
+        return;
+    };
+    let records = {
+        let msg_handles = app_handle.state::<MsgHandles>();
+        let mut msg_handles = msg_handles.0.lock().unwrap();
+        let Some(x) = msg_handles.get_mut(id) else {
+            return;
+        };
+        let records = x.records_from(next).map(|(seq, time, dir, data)| (seq, time, dir, data.to_vec())).collect::<Vec<_>>();
+        x.seal();
+        records
+    };
+    if records.is_empty() {
+        return;
+    }
+    let mut captures = captures.0.lock().unwrap();
+    let Some((writer, next)) = captures.get_mut(id) else {
+        return;
+    };
+    let mut result = Ok(());
+    // 其他线程可能已写入部分记录
+    for (seq, time, dir, data) in records {
+        if seq < *next {
+            continue;
+        }
+        result = writer.write_packet(time.timestamp_nanos_opt().unwrap_or_default() as u64, dir, &data);
+        if result.is_err() {
+            break;
+        }
+        *next = seq + 1;
+    }
+    if result.is_ok() {
+        result = writer.flush();
+    }
+    if let Err(e) = result {
+        captures.remove(id);
+        app_handle.emit_all(&format!("capture_error_{id}"), e.to_string()).unwrap_or_default();
+    }
+}
+
+// 发送新解析出的绘图数据，与接收事件同步合并
+fn emit_plot(app_handle: &tauri::AppHandle, id: &str) {
+    let batch = match app_handle.state::<Plotters>().0.lock().unwrap().get_mut(id) {
+        Some(x) => x.take_batch(),
+        None => return,
+    };
+    if !batch.is_empty() {
+        app_handle.emit_all(&format!("plot_{id}"), batch).unwrap_or_default();
+    }
+}
+
+// 发送终端屏幕变化，与接收事件同步合并
+fn emit_terminal(app_handle: &tauri::AppHandle, id: &str) {
+    let diff = match app_handle.state::<Terminals>().0.lock().unwrap().get_mut(id) {
+        Some(x) => x.take_diff(),
+        None => return,
+    };
+    if let Some(diff) = diff {
+        app_handle.emit_all(&format!("term_{id}"), diff).unwrap_or_default();
+    }
+}
+
+// stats_{id} 事件的发送间隔
+const STATS_INTERVAL: Duration = Duration::from_secs(1);
+
+// 告警弹出系统通知，标题为串口名与级别
+fn notify_alert(app_handle: &tauri::AppHandle, id: &str, record: &AlertRecord) {
+    let port = port_name(app_handle, id).unwrap_or_default();
+    tauri::api::notification::Notification::new(&app_handle.config().tauri.bundle.identifier)
+        .title(format!("{port} {}", record.severity.as_str()))
+        .body(format!("{}：{}", record.name, record.text))
+        .show()
+        .unwrap_or_default();
+}
+
+// 解析发送内容
+// hex：字符串（宽松格式）或字节数组；文本：escape 为 true 时解析转义序列
+// 文本按 code 指定的编码发送，未指定时使用会话的发送编码
+// 文本按会话换行设置追加或替换换行符，十六进制原样发送
+fn parse_send_msg(v_json: &Map<String, Value>, code: &'static Encoding, newline: &NewlineOption) -> Result<Vec<u8>, Vec<ParseError>> {
+    let msg = v_json.get("msg");
+    match v_json.get("type").and_then(|v| v.as_str()).unwrap_or("") {
+        "hex" => match msg {
+            Some(Value::String(v)) => parse_hex(v),
+            Some(Value::Array(v)) => Ok(v.iter().filter_map(|v| v.as_u64()).map(|v| v as u8).collect()),
+            _ => Ok(Vec::new()),
+        },
+        _ => {
+            let code = match v_json.get("code").and_then(|v| v.as_str()) {
+                Some(label) => encoding_for_label(label).ok_or_else(|| vec![ParseError {
+                    start: 0,
+                    end: 0,
+                    msg: format!("不支持的编码：{label}"),
+                }])?,
+                None => code,
+            };
+            let msg = msg.and_then(|v| v.as_str()).unwrap_or("");
+            let segments = if v_json.get("escape").and_then(|v| v.as_bool()).unwrap_or(false) {
+                parse_escape(msg)?
+            } else {
+                vec![Segment::text(msg)]
+            };
+            let segments = match newline.send {
+                NewlineMode::Keep => segments,
+                NewlineMode::Append => {
+                    let mut segments = segments;
+                    append_newline(&mut segments, newline.send_newline);
+                    segments
+                }
+                NewlineMode::Translate => translate_newlines(segments, newline.send_newline),
+            };
+            encode_segments(&segments, code)
+        }
+    }
+}
+
 async fn _connect(app_handle: tauri::AppHandle, id: &str, port: impl AsRef<str>, br: u32) -> Result<String> {
     let p = Serial::new(port.as_ref(), br).connect()?;
     // 10微秒收一次数据
@@ -58,7 +231,8 @@ async fn _connect(app_handle: tauri::AppHandle, id: &str, port: impl AsRef<str>,
     let send = p.thread_send_init()?;
 
     // 暂存 统计句柄
-    let msg_handle = MsgHandle::new();
+    let mut msg_handle = MsgHandle::new();
+    msg_handle.set_port_name(port.as_ref());
     let msg_handles = app_handle.state::<MsgHandles>();
     let mut msg_handles_guard = msg_handles.0.lock().unwrap();
     msg_handles_guard.insert(id.to_string(), msg_handle);
@@ -66,18 +240,118 @@ async fn _connect(app_handle: tauri::AppHandle, id: &str, port: impl AsRef<str>,
     // 创建接收线程
     let id_str = id.to_string();
     let app_handle_clone = app_handle.clone();
+    let trigger_send = send.clone();
     thread::spawn(move || {
         let msg_handles = app_handle_clone.state::<MsgHandles>();
+        let recv_taps = app_handle_clone.state::<RecvTaps>();
+        let triggers = app_handle_clone.state::<Triggers>();
+        let alerts = app_handle_clone.state::<Alerts>();
+        let plotters = app_handle_clone.state::<Plotters>();
+        let terminals = app_handle_clone.state::<Terminals>();
+        // 数据逐次写入历史，前端事件按频率或数据量合并发送
+        let mut coalescer = Coalescer::new(EmitOption::default());
+        // 按行接收时定期检查未结束的行是否超时
+        let mut last_flush = std::time::Instant::now();
+        let mut last_stats = std::time::Instant::now();
         loop {
+            if last_stats.elapsed() >= STATS_INTERVAL {
+                last_stats = std::time::Instant::now();
+                if let Some(x) = msg_handles.0.lock().unwrap().get_mut(&id_str) {
+                    app_handle_clone.emit_all(&format!("stats_{id_str}"), x.stats_report()).unwrap_or_default();
+                }
+            }
             match recv.try_recv() {
                 Ok((v, s)) => {
+                    log_io(&app_handle_clone, &id_str, Direction::Rx, &v[0..s]);
+                    // 协议传输期间，接收数据转交给传输线程
+                    if let Some(tap) = recv_taps.0.lock().unwrap().get(&id_str) {
+                        if tap.send(v[0..s].to_vec()).is_ok() {
+                            continue;
+                        }
+                    }
+                    let fires = match triggers.0.lock().unwrap().get_mut(&id_str) {
+                        Some(x) => x.feed(&v[0..s], chrono::Local::now()),
+                        None => Vec::new(),
+                    };
+                    for fire in fires {
+                        app_handle_clone.emit_all(&format!("trigger_{id_str}"), &fire).unwrap_or_default();
+                        let app = app_handle_clone.clone();
+                        let id = id_str.clone();
+                        let send = trigger_send.clone();
+                        // 有延时的应答放到单独线程，避免阻塞接收
+                        let reply = move || {
+                            on_send(&app, &id, &fire.reply);
+                            send.send(fire.reply).unwrap_or_default();
+                        };
+                        if fire.delay == 0 {
+                            reply();
+                        } else {
+                            thread::spawn(move || {
+                                thread::sleep(Duration::from_millis(fire.delay));
+                                reply();
+                            });
+                        }
+                    }
+                    let records = match alerts.0.lock().unwrap().get_mut(&id_str) {
+                        Some(x) => x.feed(&v[0..s], std::time::Instant::now(), chrono::Local::now()),
+                        None => Vec::new(),
+                    };
+                    for record in records {
+                        app_handle_clone.emit_all(&format!("alert_{id_str}"), &record).unwrap_or_default();
+                        if record.notify {
+                            notify_alert(&app_handle_clone, &id_str, &record);
+                        }
+                    }
+                    if let Some(x) = plotters.0.lock().unwrap().get_mut(&id_str) {
+                        x.feed(&v[0..s], chrono::Local::now());
+                    }
+                    // 终端的应答（如光标位置报告）直接回复给设备
+                    let responses = match terminals.0.lock().unwrap().get_mut(&id_str) {
+                        Some(x) => {
+                            x.feed(&v[0..s]);
+                            x.take_responses()
+                        }
+                        None => Vec::new(),
+                    };
+                    if !responses.is_empty() {
+                        on_send(&app_handle_clone, &id_str, &responses);
+                        trigger_send.send(responses).unwrap_or_default();
+                    }
+                    let mut sent = 0;
                     if let Some(x) = msg_handles.0.lock().unwrap().get_mut(&id_str) {
                         x.add_buffer(v[0..s].to_vec());
+                        coalescer.set_option(x.emit_option());
+                        sent = x.take_pending_send();
+                    }
+                    capture_records(&app_handle_clone, &id_str);
+                    if coalescer.push(s + sent) {
+                        update_msg(&app_handle_clone, &id_str);
+                        emit_plot(&app_handle_clone, &id_str);
+                        emit_terminal(&app_handle_clone, &id_str);
+                        coalescer.reset();
                     }
-                    update_msg(&app_handle_clone, &id_str, None);
                     // dbg!(msg_handles.0.lock().unwrap());
                 },
                 Err(TryRecvError::Empty) => {
+                    if last_flush.elapsed() >= Duration::from_millis(10) {
+                        last_flush = std::time::Instant::now();
+                        // 发送记录也在这里计入合并，不单独发送事件
+                        let (flushed, sent) = match msg_handles.0.lock().unwrap().get_mut(&id_str) {
+                            Some(x) => (x.flush_line(false), x.take_pending_send()),
+                            None => (0, 0),
+                        };
+                        if flushed > 0 {
+                            capture_records(&app_handle_clone, &id_str);
+                        }
+                        coalescer.push(flushed + sent);
+                    }
+                    // 空闲时补发剩余数据
+                    if coalescer.due() {
+                        update_msg(&app_handle_clone, &id_str);
+                        emit_plot(&app_handle_clone, &id_str);
+                        emit_terminal(&app_handle_clone, &id_str);
+                        coalescer.reset();
+                    }
                     thread::sleep(Duration::from_micros(1));
                     continue;
                 }
@@ -88,7 +362,7 @@ async fn _connect(app_handle: tauri::AppHandle, id: &str, port: impl AsRef<str>,
         }
     });
 
-    // 构建发送监听事件 todo 可变发送字符编码，默认utf8
+    // 构建发送监听事件
     let id_str = id.to_string();
     let app_handle_clone = app_handle.clone();
     let app_handle_clone_1 = app_handle.clone();
@@ -97,27 +371,21 @@ async fn _connect(app_handle: tauri::AppHandle, id: &str, port: impl AsRef<str>,
         let v_json: Value = serde_json::from_str(v).unwrap();
         let v_json = v_json.as_object().unwrap();
 
-        // 获取发送类型
-        let msg_type = v_json.get("type").unwrap().as_str().unwrap_or("");
         // 是否循环
         let msg_loop = v_json.get("loop").unwrap().as_bool().unwrap_or(false);
-        // 获取发送内容
-        let msg = match msg_type {
-            "hex" => {
-                v_json.get("msg").unwrap().as_array().unwrap()
-                    .iter().map(|v| v.as_number().unwrap().as_u64().unwrap() as u8).collect::<Vec<u8>>()
-            }
-            _ => {
-                let msg = v_json.get("msg").unwrap().as_str();
-                if let Some(msg) = msg {
-                    Vec::from(msg.as_bytes())
-                } else {
-                    Vec::new()
-                }
+        // 获取发送内容，解析失败时把错误位置发回前端
+        let (code, newline) = app_handle_clone_1.state::<MsgHandles>().0.lock().unwrap()
+            .get(&id_str).map(|v| (v.send_code(), v.newline_option().clone()))
+            .unwrap_or((encoding_rs::UTF_8, NewlineOption::default()));
+        let msg = match parse_send_msg(v_json, code, &newline) {
+            Ok(msg) => msg,
+            Err(errors) => {
+                app_handle_clone_1.emit_all(&format!("send_error_{id_str}"), errors).unwrap_or_default();
+                return;
             }
         };
 
-        // dbg!(&v_json, &msg_type, &msg_loop, &msg);
+        // dbg!(&v_json, &msg_loop, &msg);
 
 
         if msg_loop {
@@ -139,7 +407,7 @@ async fn _connect(app_handle: tauri::AppHandle, id: &str, port: impl AsRef<str>,
                     }
                     Err(TryRecvError::Empty) => {
                         thread::sleep(Duration::from_millis(delay));
-                        update_msg(&app, &id_str, Some(&msg));
+                        on_send(&app, &id_str, &msg);
                         send.send(msg.clone()).unwrap_or_default();
                         continue;
                     }
@@ -149,7 +417,7 @@ async fn _connect(app_handle: tauri::AppHandle, id: &str, port: impl AsRef<str>,
                 }
             });
         } else {
-            update_msg(&app_handle_clone_1, &id_str, Some(&msg));
+            on_send(&app_handle_clone_1, &id_str, &msg);
             send.send(msg).unwrap_or_default();
         }
 
@@ -185,6 +453,8 @@ async fn _disconnect(app_handle: tauri::AppHandle, id: &str) -> Result<String> {
 
     let p = serials.get(id).context("未找到串口")?;
     if p.is_connected()? {
+        // 停止正在进行的文件发送
+        app_handle.state::<Transfers>().cancel(id);
         p.disconnect()?;
         // 取消监听事件
         app_handle.unlisten(send_handles.get(id).unwrap().to_owned());
@@ -192,6 +462,17 @@ async fn _disconnect(app_handle: tauri::AppHandle, id: &str) -> Result<String> {
         send_handles.remove(id);
         // 移除统计句柄
         msg_handles.remove(id);
+        // 结束会话记录和抓包，记录写入失败时通知前端并继续清理
+        if let Some(x) = app_handle.state::<Loggers>().0.lock().unwrap().remove(id) {
+            if let Err(e) = x.finish() {
+                app_handle.emit_all(&format!("log_error_{id}"), e.to_string()).unwrap_or_default();
+            }
+        }
+        app_handle.state::<Captures>().0.lock().unwrap().remove(id);
+        app_handle.state::<Triggers>().0.lock().unwrap().remove(id);
+        app_handle.state::<Alerts>().0.lock().unwrap().remove(id);
+        app_handle.state::<Plotters>().0.lock().unwrap().remove(id);
+        app_handle.state::<Terminals>().0.lock().unwrap().remove(id);
         // 移除串口句柄
         serials.remove(id);
     }
@@ -203,6 +484,198 @@ pub async fn disconnect(app_handle: tauri::AppHandle, id: &str) -> Result<String
 }
 
 
+async fn _send_file(app_handle: tauri::AppHandle, id: String, path: String, option: SendFileOption) -> Result<u64> {
+    let mut port = {
+        let serials = app_handle.state::<Serials>();
+        let serials = serials.0.lock().unwrap();
+        serials.get(&id).context("未找到串口")?.clone_port()?
+    };
+    let total = std::fs::metadata(&path).with_context(|| format!("读取文件失败：{path}"))?.len();
+
+    // 同一串口同时只允许一个文件发送
+    let cancel = Arc::new(AtomicBool::new(false));
+    {
+        let transfers = app_handle.state::<Transfers>();
+        let mut transfers = transfers.0.lock().unwrap();
+        if transfers.contains_key(&id) {
+            return Err(anyhow::format_err!("已有文件正在发送"));
+        }
+        transfers.insert(id.clone(), cancel.clone());
+    }
+
+    thread::spawn(move || {
+        let event = format!("send_file_{id}");
+        let mut last_emit = std::time::Instant::now();
+        // 出错时报告已写入的字节数
+        let mut done = 0;
+        let result = crate::transfer::send_file(port.as_mut(), &path, &option, &cancel, |chunk, sent, total| {
+            done = sent;
+            // 按实际写入计入发送计数
+            on_send_chunk(&app_handle, &id, chunk);
+            // 进度事件限制在每 50ms 一次
+            if last_emit.elapsed() >= Duration::from_millis(50) {
+                last_emit = std::time::Instant::now();
+                let progress = TransferProgress::new(TransferState::Sending, sent, total);
+                app_handle.emit_all(&event, progress).unwrap_or_default();
+            }
+        });
+        let progress = match result {
+            Ok(sent) if sent >= total => TransferProgress::new(TransferState::Done, sent, total),
+            Ok(sent) => TransferProgress::new(TransferState::Cancel, sent, total),
+            Err(e) => TransferProgress::error(done, total, e.to_string()),
+        };
+        app_handle.state::<Transfers>().0.lock().unwrap().remove(&id);
+        app_handle.emit_all(&event, progress).unwrap_or_default();
+    });
+
+    Ok(total)
+}
+#[tauri::command]
+pub async fn send_file(app_handle: tauri::AppHandle, id: String, path: String, option: Option<SendFileOption>) -> Result<u64, String> {
+    catch_error_to_string!(_send_file, app_handle, id, path, option.unwrap_or_default())
+}
+
+#[tauri::command]
+pub async fn cancel_send_file(app_handle: tauri::AppHandle, id: String) -> Result<bool, String> {
+    Ok(app_handle.state::<Transfers>().cancel(&id))
+}
+
+
+// 在独立线程中运行协议传输，过程事件和结果通过 modem_{id} 事件发往前端
+fn spawn_modem<F>(app_handle: tauri::AppHandle, id: String, f: F) -> Result<()>
+    where F: FnOnce(&mut Modem<TapPort>) -> Result<String> + Send + 'static
+{
+    let port = {
+        let serials = app_handle.state::<Serials>();
+        let serials = serials.0.lock().unwrap();
+        serials.get(&id).context("未找到串口")?.clone_port()?
+    };
+
+    let cancel = Arc::new(AtomicBool::new(false));
+    {
+        let transfers = app_handle.state::<Transfers>();
+        let mut transfers = transfers.0.lock().unwrap();
+        if transfers.contains_key(&id) {
+            return Err(anyhow::format_err!("已有传输正在进行"));
+        }
+        transfers.insert(id.clone(), cancel.clone());
+    }
+    let (tx, rx) = channel();
+    app_handle.state::<RecvTaps>().0.lock().unwrap().insert(id.clone(), tx);
+
+    thread::spawn(move || {
+        let event = format!("modem_{id}");
+        let mut port = TapPort::new(port, rx);
+        // 协议数据与普通发送一样记录并计数
+        let app = app_handle.clone();
+        let id_clone = id.clone();
+        port.set_on_write(move |v| on_send_chunk(&app, &id_clone, v));
+        let mut modem = Modem::new(port);
+        modem.set_cancel(cancel.clone());
+        let app = app_handle.clone();
+        let event_clone = event.clone();
+        let id_clone = id.clone();
+        let mut last_emit = std::time::Instant::now();
+        modem.set_handler(move |e| {
+            // 重传计入会话统计，校验失败为校验错误，无效应答为帧错误
+            if let ModemEvent::Retry { kind, .. } = &e {
+                if let Some(x) = app.state::<MsgHandles>().0.lock().unwrap().get_mut(&id_clone) {
+                    match kind {
+                        RetryKind::Checksum => x.stats_mut().add_checksum_error(),
+                        RetryKind::Framing => x.stats_mut().add_framing_error(),
+                        RetryKind::Timeout => {}
+                    }
+                }
+            }
+            // 进度事件限制在每 50ms 一次
+            if let ModemEvent::Progress { done, total } = e {
+                if done < total && last_emit.elapsed() < Duration::from_millis(50) {
+                    return;
+                }
+                last_emit = std::time::Instant::now();
+            }
+            app.emit_all(&event_clone, e).unwrap_or_default();
+        });
+
+        let result = f(&mut modem);
+        drop(modem);
+        app_handle.state::<RecvTaps>().0.lock().unwrap().remove(&id);
+        app_handle.state::<Transfers>().0.lock().unwrap().remove(&id);
+        let state = match &result {
+            Ok(_) => "done",
+            Err(_) if cancel.load(std::sync::atomic::Ordering::Relaxed) => "cancel",
+            Err(_) => "error",
+        };
+        let msg = result.unwrap_or_else(|e| e.to_string());
+        app_handle.emit_all(&event, json!({ "type": state, "msg": msg })).unwrap_or_default();
+    });
+    Ok(())
+}
+
+async fn _modem_send(app_handle: tauri::AppHandle, id: String, protocol: ModemProtocol, paths: Vec<String>) -> Result<()> {
+    let mut files = paths.iter().map(ModemFile::open).collect::<Result<Vec<ModemFile>>>()?;
+    if files.is_empty() {
+        return Err(anyhow::format_err!("未选择文件"));
+    }
+    spawn_modem(app_handle, id, move |modem| {
+        let sent = match protocol {
+            ModemProtocol::Ymodem => modem.ymodem_send(files)?,
+            ModemProtocol::Zmodem => modem.zmodem_send(files)?,
+            // XMODEM 只发送第一个文件
+            _ => {
+                let mut file = files.remove(0);
+                let one_k = protocol == ModemProtocol::Xmodem1k;
+                modem.xmodem_send(&mut file.data, file.size, one_k)?
+            }
+        };
+        Ok(format!("发送完成，共 {sent} 字节"))
+    })
+}
+#[tauri::command]
+pub async fn modem_send(app_handle: tauri::AppHandle, id: String, protocol: ModemProtocol, paths: Vec<String>) -> Result<(), String> {
+    catch_error_to_string!(_modem_send, app_handle, id, protocol, paths)
+}
+
+// XMODEM 的 path 为保存文件路径，YMODEM/ZMODEM 的 path 为保存目录
+async fn _modem_recv(app_handle: tauri::AppHandle, id: String, protocol: ModemProtocol, path: String) -> Result<()> {
+    spawn_modem(app_handle, id, move |modem| {
+        let msg = match protocol {
+            ModemProtocol::Ymodem => format!("接收完成，共 {} 个文件", modem.ymodem_recv(path.as_ref())?.len()),
+            ModemProtocol::Zmodem => format!("接收完成，共 {} 个文件", modem.zmodem_recv(path.as_ref())?.len()),
+            _ => {
+                let mut file = std::fs::File::create(&path).with_context(|| format!("创建文件失败：{path}"))?;
+                let crc = protocol != ModemProtocol::Xmodem;
+                format!("接收完成，共 {} 字节", modem.xmodem_recv(&mut file, crc)?)
+            }
+        };
+        Ok(msg)
+    })
+}
+#[tauri::command]
+pub async fn modem_recv(app_handle: tauri::AppHandle, id: String, protocol: ModemProtocol, path: String) -> Result<(), String> {
+    catch_error_to_string!(_modem_recv, app_handle, id, protocol, path)
+}
+
+#[tauri::command]
+pub async fn cancel_modem(app_handle: tauri::AppHandle, id: String) -> Result<bool, String> {
+    Ok(app_handle.state::<Transfers>().cancel(&id))
+}
+
+
+// 预先校验发送输入，供前端标出错误位置
+#[tauri::command]
+pub async fn parse_input(msg: String, hex: bool, code: Option<String>) -> Result<Vec<u8>, Vec<ParseError>> {
+    let mut v_json = Map::new();
+    v_json.insert("type".into(), json!(if hex { "hex" } else { "text" }));
+    v_json.insert("msg".into(), json!(msg));
+    v_json.insert("escape".into(), json!(true));
+    if let Some(code) = code {
+        v_json.insert("code".into(), json!(code));
+    }
+    parse_send_msg(&v_json, encoding_rs::UTF_8, &NewlineOption::default())
+}
+
+
 #[tauri::command]
 pub async fn get_serial_ports() -> Result<Vec<SerialInfo>, String> {
     match list_available_ports() {
@@ -244,11 +717,11 @@ pub async fn _set_recv_setting(app_handle: tauri::AppHandle, id: String, item: u
                     _ => msg_handle.set_display_hex(false)
                 }
             }
-            // 显示编码
+            // 显示编码，仅兼容 0 UTF-8 / 1 GBK，其余编码使用 set_display_code
             103 => {
                 match value {
-                    1 => msg_handle.set_display_code(MsgCode::GBK),
-                    _ => msg_handle.set_display_code(MsgCode::UTF8)
+                    1 => msg_handle.set_display_code(MsgCode(encoding_rs::GBK)),
+                    _ => msg_handle.set_display_code(MsgCode(encoding_rs::UTF_8))
                 }
             }
             104 => {
@@ -258,15 +731,585 @@ pub async fn _set_recv_setting(app_handle: tauri::AppHandle, id: String, item: u
                     _ => msg_handle.set_recv_len(None)
                 }
             }
+            // 穿插显示发送记录
+            105 => {
+                match value {
+                    1 => msg_handle.set_display_send(true),
+                    _ => msg_handle.set_display_send(false)
+                }
+            }
             _ => {}
         }
     }
 
-    update_msg(&app_handle, &id, None);
+    update_msg(&app_handle, &id);
 
     Ok(())
 }
 #[tauri::command]
 pub async fn set_recv_setting(app_handle: tauri::AppHandle, id: String, item: u32, value: i64) -> Result<(), String> {
     catch_error_to_string!(_set_recv_setting, app_handle, id, item, value)
-}
\ No newline at end of file
+}
+
+// 设置会话发送编码
+pub async fn _set_send_code(app_handle: tauri::AppHandle, id: String, code: String) -> Result<()> {
+    let code = encoding_for_label(&code).with_context(|| format!("不支持的编码：{code}"))?;
+    let msg_handles = app_handle.state::<MsgHandles>();
+    let mut msg_handle = msg_handles.0.lock().unwrap();
+    msg_handle.get_mut(&id).context("未找到指定id")?.set_send_code(code);
+    Ok(())
+}
+#[tauri::command]
+pub async fn set_send_code(app_handle: tauri::AppHandle, id: String, code: String) -> Result<(), String> {
+    catch_error_to_string!(_set_send_code, app_handle, id, code)
+}
+
+
+// 设置显示编码，code 为 encoding_rs 标签
+pub async fn _set_display_code(app_handle: tauri::AppHandle, id: String, code: String) -> Result<()> {
+    let code = encoding_for_label(&code).with_context(|| format!("不支持的编码：{code}"))?;
+    {
+        let msg_handles = app_handle.state::<MsgHandles>();
+        let mut msg_handle = msg_handles.0.lock().unwrap();
+        msg_handle.get_mut(&id).context("未找到指定id")?.set_display_code(MsgCode(code));
+    }
+    // 终端模式下之后的接收数据同样按新编码解码
+    if let Some(x) = app_handle.state::<Terminals>().0.lock().unwrap().get_mut(&id) {
+        x.set_code(code);
+    }
+    update_msg(&app_handle, &id);
+    Ok(())
+}
+#[tauri::command]
+pub async fn set_display_code(app_handle: tauri::AppHandle, id: String, code: String) -> Result<(), String> {
+    catch_error_to_string!(_set_display_code, app_handle, id, code)
+}
+
+// 支持的收发编码名称
+#[tauri::command]
+pub async fn get_encodings() -> Result<Vec<&'static str>, String> {
+    Ok(encoding_names())
+}
+
+
+// 获取当前显示范围内的全部记录，显示设置变化后由前端调用
+pub async fn _get_recv_snapshot(app_handle: tauri::AppHandle, id: String) -> Result<Value> {
+    let msg_handles = app_handle.state::<MsgHandles>();
+    let mut msg_handle = msg_handles.0.lock().unwrap();
+    let x = msg_handle.get_mut(&id).context("未找到指定id")?;
+    Ok(json!({
+        "recv_count": x.recv_count,
+        "send_count": x.send_count,
+        "seq": x.seq(),
+        "reset": true,
+        "trim": 0,
+        "records": x.snapshot()
+    }))
+}
+#[tauri::command]
+pub async fn get_recv_snapshot(app_handle: tauri::AppHandle, id: String) -> Result<Value, String> {
+    catch_error_to_string!(_get_recv_snapshot, app_handle, id)
+}
+
+// 设置显示时间格式，之后前端需重新获取完整内容
+pub async fn _set_time_format(app_handle: tauri::AppHandle, id: String, format: TimeFormat) -> Result<()> {
+    let msg_handles = app_handle.state::<MsgHandles>();
+    let mut msg_handles = msg_handles.0.lock().unwrap();
+    msg_handles.get_mut(&id).context("未找到指定id")?.set_time_format(format);
+    Ok(())
+}
+#[tauri::command]
+pub async fn set_time_format(app_handle: tauri::AppHandle, id: String, format: TimeFormat) -> Result<(), String> {
+    catch_error_to_string!(_set_time_format, app_handle, id, format)
+}
+
+// 设置按行接收，option 为空时关闭，之后前端需重新获取完整内容
+pub async fn _set_line_option(app_handle: tauri::AppHandle, id: String, option: Option<LineOption>) -> Result<()> {
+    let msg_handles = app_handle.state::<MsgHandles>();
+    let mut msg_handles = msg_handles.0.lock().unwrap();
+    msg_handles.get_mut(&id).context("未找到指定id")?.set_line_option(option);
+    Ok(())
+}
+#[tauri::command]
+pub async fn set_line_option(app_handle: tauri::AppHandle, id: String, option: Option<LineOption>) -> Result<(), String> {
+    catch_error_to_string!(_set_line_option, app_handle, id, option)
+}
+
+// 设置发送文本与接收显示的换行处理，显示换行符变化后前端需重新获取完整内容
+pub async fn _set_newline_option(app_handle: tauri::AppHandle, id: String, option: NewlineOption) -> Result<()> {
+    let msg_handles = app_handle.state::<MsgHandles>();
+    let mut msg_handles = msg_handles.0.lock().unwrap();
+    msg_handles.get_mut(&id).context("未找到指定id")?.set_newline_option(option);
+    Ok(())
+}
+#[tauri::command]
+pub async fn set_newline_option(app_handle: tauri::AppHandle, id: String, option: NewlineOption) -> Result<(), String> {
+    catch_error_to_string!(_set_newline_option, app_handle, id, option)
+}
+
+// 按当前显示设置渲染的纯文本，用于复制接收区
+pub async fn _get_recv_text(app_handle: tauri::AppHandle, id: String) -> Result<String> {
+    let msg_handles = app_handle.state::<MsgHandles>();
+    let msg_handles = msg_handles.0.lock().unwrap();
+    Ok(msg_handles.get(&id).context("未找到指定id")?.recv_buffer_to_string())
+}
+#[tauri::command]
+pub async fn get_recv_text(app_handle: tauri::AppHandle, id: String) -> Result<String, String> {
+    catch_error_to_string!(_get_recv_text, app_handle, id)
+}
+
+
+// 设置接收历史上限及淘汰记录的落盘文件
+pub async fn _set_history_limit(app_handle: tauri::AppHandle, id: String, limit: HistoryLimit, spill: Option<String>) -> Result<HistoryStats> {
+    let msg_handles = app_handle.state::<MsgHandles>();
+    let mut msg_handle = msg_handles.0.lock().unwrap();
+    let x = msg_handle.get_mut(&id).context("未找到指定id")?;
+    x.set_spill(spill)?;
+    x.set_history_limit(limit);
+    Ok(x.history_stats())
+}
+#[tauri::command]
+pub async fn set_history_limit(app_handle: tauri::AppHandle, id: String, limit: HistoryLimit, spill: Option<String>) -> Result<HistoryStats, String> {
+    catch_error_to_string!(_set_history_limit, app_handle, id, limit, spill)
+}
+
+pub async fn _get_history_stats(app_handle: tauri::AppHandle, id: String) -> Result<HistoryStats> {
+    let msg_handles = app_handle.state::<MsgHandles>();
+    let msg_handle = msg_handles.0.lock().unwrap();
+    Ok(msg_handle.get(&id).context("未找到指定id")?.history_stats())
+}
+#[tauri::command]
+pub async fn get_history_stats(app_handle: tauri::AppHandle, id: String) -> Result<HistoryStats, String> {
+    catch_error_to_string!(_get_history_stats, app_handle, id)
+}
+
+
+// 设置接收事件合并发送的频率与字节阈值
+pub async fn _set_emit_option(app_handle: tauri::AppHandle, id: String, option: EmitOption) -> Result<()> {
+    let msg_handles = app_handle.state::<MsgHandles>();
+    let mut msg_handle = msg_handles.0.lock().unwrap();
+    msg_handle.get_mut(&id).context("未找到指定id")?.set_emit_option(option);
+    Ok(())
+}
+#[tauri::command]
+pub async fn set_emit_option(app_handle: tauri::AppHandle, id: String, option: EmitOption) -> Result<(), String> {
+    catch_error_to_string!(_set_emit_option, app_handle, id, option)
+}
+
+
+// 按范围查询接收历史，供前端虚拟列表使用
+pub async fn _query_history(app_handle: tauri::AppHandle, id: String, start: usize, count: usize, format: Option<RowFormat>) -> Result<HistoryPage> {
+    let msg_handles = app_handle.state::<MsgHandles>();
+    let msg_handle = msg_handles.0.lock().unwrap();
+    Ok(msg_handle.get(&id).context("未找到指定id")?.query(start, count, format))
+}
+#[tauri::command]
+pub async fn query_history(app_handle: tauri::AppHandle, id: String, start: usize, count: usize, format: Option<RowFormat>) -> Result<HistoryPage, String> {
+    catch_error_to_string!(_query_history, app_handle, id, start, count, format)
+}
+
+
+// 开始记录会话收发数据，已在记录时先结束原记录，返回记录文件路径
+pub async fn _start_log(app_handle: tauri::AppHandle, id: String, option: Option<LogOption>) -> Result<String> {
+    let (tx_code, rx_code, port) = {
+        let msg_handles = app_handle.state::<MsgHandles>();
+        let msg_handles = msg_handles.0.lock().unwrap();
+        let x = msg_handles.get(&id).context("未找到指定id")?;
+        (x.send_code(), x.display_code(), x.port_name().to_string())
+    };
+    let logger = SessionLogger::open(option.unwrap_or_default(), &port, &id, tx_code, rx_code)?;
+    let path = logger.path();
+    let loggers = app_handle.state::<Loggers>();
+    let old = loggers.0.lock().unwrap().insert(id.clone(), logger);
+    // 新记录已开始，原记录结束失败只通知前端
+    if let Some(old) = old {
+        if let Err(e) = old.finish() {
+            app_handle.emit_all(&format!("log_error_{id}"), e.to_string()).unwrap_or_default();
+        }
+    }
+    Ok(path)
+}
+#[tauri::command]
+pub async fn start_log(app_handle: tauri::AppHandle, id: String, option: Option<LogOption>) -> Result<String, String> {
+    catch_error_to_string!(_start_log, app_handle, id, option)
+}
+
+// 结束记录，返回记录文件及归档文件
+pub async fn _stop_log(app_handle: tauri::AppHandle, id: String) -> Result<Option<LogFiles>> {
+    let loggers = app_handle.state::<Loggers>();
+    let logger = loggers.0.lock().unwrap().remove(&id);
+    match logger {
+        None => Ok(None),
+        Some(x) => Ok(Some(x.finish()?)),
+    }
+}
+#[tauri::command]
+pub async fn stop_log(app_handle: tauri::AppHandle, id: String) -> Result<Option<LogFiles>, String> {
+    catch_error_to_string!(_stop_log, app_handle, id)
+}
+
+
+fn create_pcapng(path: &str, linktype: Option<u16>, if_name: &str) -> Result<PcapngWriter<BufWriter<File>>> {
+    let file = File::create(path).with_context(|| format!("创建文件失败：{path}"))?;
+    PcapngWriter::new(BufWriter::new(file), linktype.unwrap_or(LINKTYPE_USER0), if_name)
+}
+
+fn port_name(app_handle: &tauri::AppHandle, id: &str) -> Result<String> {
+    let msg_handles = app_handle.state::<MsgHandles>();
+    let msg_handles = msg_handles.0.lock().unwrap();
+    Ok(msg_handles.get(id).context("未找到指定id")?.port_name().to_string())
+}
+
+// 开始实时抓包到 pcapng 文件，linktype 缺省为 LINKTYPE_USER0
+pub async fn _start_capture(app_handle: tauri::AppHandle, id: String, path: String, linktype: Option<u16>) -> Result<String> {
+    let (port, seq) = {
+        let msg_handles = app_handle.state::<MsgHandles>();
+        let msg_handles = msg_handles.0.lock().unwrap();
+        let x = msg_handles.get(&id).context("未找到指定id")?;
+        (x.port_name().to_string(), x.seq())
+    };
+    let writer = create_pcapng(&path, linktype, &port)?;
+    // 只写入开始之后的记录
+    app_handle.state::<Captures>().0.lock().unwrap().insert(id, (writer, seq));
+    Ok(path)
+}
+#[tauri::command]
+pub async fn start_capture(app_handle: tauri::AppHandle, id: String, path: String, linktype: Option<u16>) -> Result<String, String> {
+    catch_error_to_string!(_start_capture, app_handle, id, path, linktype)
+}
+
+#[tauri::command]
+pub async fn stop_capture(app_handle: tauri::AppHandle, id: String) -> Result<bool, String> {
+    Ok(app_handle.state::<Captures>().0.lock().unwrap().remove(&id).is_some())
+}
+
+// 导出当前收发历史为 pcapng，返回导出的记录数
+pub async fn _export_pcapng(app_handle: tauri::AppHandle, id: String, path: String, linktype: Option<u16>) -> Result<usize> {
+    // 先复制历史再写文件，避免写文件期间阻塞收发
+    let entries = {
+        let msg_handles = app_handle.state::<MsgHandles>();
+        let msg_handles = msg_handles.0.lock().unwrap();
+        let x = msg_handles.get(&id).context("未找到指定id")?;
+        x.entries().map(|(time, dir, data)| (time, dir, data.to_vec())).collect::<Vec<_>>()
+    };
+    let mut writer = create_pcapng(&path, linktype, &port_name(&app_handle, &id)?)?;
+    for (time, dir, data) in &entries {
+        writer.write_packet(time.timestamp_nanos_opt().unwrap_or_default() as u64, *dir, data)?;
+    }
+    writer.flush()?;
+    Ok(entries.len())
+}
+#[tauri::command]
+pub async fn export_pcapng(app_handle: tauri::AppHandle, id: String, path: String, linktype: Option<u16>) -> Result<usize, String> {
+    catch_error_to_string!(_export_pcapng, app_handle, id, path, linktype)
+}
+
+
+// 回放抓包文件中指定方向的数据到串口，进度通过 replay_{id} 事件发往前端
+// 与文件发送、协议传输共用取消标志，同一串口同时只允许一个
+pub async fn _replay(app_handle: tauri::AppHandle, id: String, path: String, option: ReplayOption) -> Result<usize> {
+    let records = read_capture(&path)?;
+    let total = records.iter().filter(|v| v.dir == option.dir).count();
+    let mut port = {
+        let serials = app_handle.state::<Serials>();
+        let serials = serials.0.lock().unwrap();
+        serials.get(&id).context("未找到串口")?.clone_port()?
+    };
+
+    let cancel = Arc::new(AtomicBool::new(false));
+    let pause = Arc::new(AtomicBool::new(false));
+    {
+        let transfers = app_handle.state::<Transfers>();
+        let mut transfers = transfers.0.lock().unwrap();
+        if transfers.contains_key(&id) {
+            return Err(anyhow::format_err!("已有传输正在进行"));
+        }
+        transfers.insert(id.clone(), cancel.clone());
+    }
+    app_handle.state::<Replays>().0.lock().unwrap().insert(id.clone(), pause.clone());
+
+    thread::spawn(move || {
+        let event = format!("replay_{id}");
+        let mut last_emit = std::time::Instant::now();
+        // 出错时报告已发送的条数
+        let mut done = 0;
+        let result = crate::replay::replay(&records, &option, &cancel, &pause, |data| {
+            port.write_all(data).context("写入串口失败")?;
+            done += 1;
+            on_send(&app_handle, &id, data);
+            Ok(())
+        }, |sent, total, paused| {
+            // 暂停状态变化立即通知，进度事件限制在每 50ms 一次
+            let state = if paused { TransferState::Paused } else { TransferState::Sending };
+            if paused || sent == 0 || last_emit.elapsed() >= Duration::from_millis(50) {
+                last_emit = std::time::Instant::now();
+                let progress = TransferProgress::new(state, sent as u64, total as u64);
+                app_handle.emit_all(&event, progress).unwrap_or_default();
+            }
+        });
+        let total = total as u64;
+        let progress = match result {
+            Ok(sent) if sent as u64 >= total => TransferProgress::new(TransferState::Done, total, total),
+            Ok(sent) => TransferProgress::new(TransferState::Cancel, sent as u64, total),
+            Err(e) => TransferProgress::error(done, total, e.to_string()),
+        };
+        app_handle.state::<Transfers>().0.lock().unwrap().remove(&id);
+        app_handle.state::<Replays>().0.lock().unwrap().remove(&id);
+        app_handle.emit_all(&event, progress).unwrap_or_default();
+    });
+
+    Ok(total)
+}
+#[tauri::command]
+pub async fn replay(app_handle: tauri::AppHandle, id: String, path: String, option: Option<ReplayOption>) -> Result<usize, String> {
+    catch_error_to_string!(_replay, app_handle, id, path, option.unwrap_or_default())
+}
+
+#[tauri::command]
+pub async fn pause_replay(app_handle: tauri::AppHandle, id: String) -> Result<bool, String> {
+    Ok(app_handle.state::<Replays>().set_paused(&id, true))
+}
+
+#[tauri::command]
+pub async fn resume_replay(app_handle: tauri::AppHandle, id: String) -> Result<bool, String> {
+    Ok(app_handle.state::<Replays>().set_paused(&id, false))
+}
+
+#[tauri::command]
+pub async fn cancel_replay(app_handle: tauri::AppHandle, id: String) -> Result<bool, String> {
+    Ok(app_handle.state::<Transfers>().cancel(&id))
+}
+
+
+// 搜索接收历史，返回匹配的记录下标与位置，max 缺省为 10000
+pub async fn _search_history(app_handle: tauri::AppHandle, id: String, query: SearchQuery, max: Option<usize>) -> Result<Vec<SearchHit>> {
+    let matcher = Matcher::new(&query)?;
+    let msg_handles = app_handle.state::<MsgHandles>();
+    let msg_handles = msg_handles.0.lock().unwrap();
+    Ok(msg_handles.get(&id).context("未找到指定id")?.search(&matcher, max.unwrap_or(10000)))
+}
+#[tauri::command]
+pub async fn search_history(app_handle: tauri::AppHandle, id: String, query: SearchQuery, max: Option<usize>) -> Result<Vec<SearchHit>, String> {
+    catch_error_to_string!(_search_history, app_handle, id, query, max)
+}
+
+// 设置显示过滤，query 为空时取消过滤，之后前端需重新获取完整内容
+pub async fn _set_display_filter(app_handle: tauri::AppHandle, id: String, query: Option<SearchQuery>, invert: bool) -> Result<()> {
+    let filter = match query {
+        Some(query) => Some(DisplayFilter { matcher: Matcher::new(&query)?, invert }),
+        None => None,
+    };
+    let msg_handles = app_handle.state::<MsgHandles>();
+    let mut msg_handles = msg_handles.0.lock().unwrap();
+    msg_handles.get_mut(&id).context("未找到指定id")?.set_display_filter(filter);
+    Ok(())
+}
+#[tauri::command]
+pub async fn set_display_filter(app_handle: tauri::AppHandle, id: String, query: Option<SearchQuery>, invert: bool) -> Result<(), String> {
+    catch_error_to_string!(_set_display_filter, app_handle, id, query, invert)
+}
+
+
+// 设置自动应答规则，接收数据匹配时发送应答，rules 为空时只清除规则，命中历史保留到断开连接
+// 应答文本按会话的发送编码编码，规则错误时返回出错规则名
+pub async fn _set_triggers(app_handle: tauri::AppHandle, id: String, rules: Vec<TriggerRule>) -> Result<()> {
+    let (send_code, display_code) = {
+        let msg_handles = app_handle.state::<MsgHandles>();
+        let msg_handles = msg_handles.0.lock().unwrap();
+        let x = msg_handles.get(&id).context("未找到指定id")?;
+        (x.send_code(), x.display_code())
+    };
+    let mut compiled = Vec::new();
+    for rule in rules {
+        let matcher = Matcher::new(&rule.query).with_context(|| format!("规则 {} 匹配条件错误", rule.name))?;
+        if matcher.matches_empty() {
+            return Err(anyhow::format_err!("规则 {} 匹配条件不能匹配空内容", rule.name));
+        }
+        let reply = if rule.hex {
+            parse_hex(&rule.reply)
+        } else {
+            parse_escape(&rule.reply).and_then(|v| encode_segments(&v, send_code))
+        };
+        let reply = reply.map_err(|e| anyhow::format_err!("规则 {} 应答内容错误：{}", rule.name, e[0].msg))?;
+        compiled.push((rule, matcher, reply));
+    }
+    let triggers = app_handle.state::<Triggers>();
+    let mut triggers = triggers.0.lock().unwrap();
+    match triggers.get_mut(&id) {
+        Some(x) => x.set_rules(compiled, display_code),
+        None => {
+            triggers.insert(id, TriggerSet::new(compiled, display_code));
+        }
+    }
+    Ok(())
+}
+#[tauri::command]
+pub async fn set_triggers(app_handle: tauri::AppHandle, id: String, rules: Vec<TriggerRule>) -> Result<(), String> {
+    catch_error_to_string!(_set_triggers, app_handle, id, rules)
+}
+
+#[tauri::command]
+pub async fn get_trigger_history(app_handle: tauri::AppHandle, id: String) -> Result<Vec<TriggerFire>, String> {
+    Ok(app_handle.state::<Triggers>().0.lock().unwrap().get(&id).map(|v| v.history()).unwrap_or_default())
+}
+
+#[tauri::command]
+pub async fn clear_trigger_history(app_handle: tauri::AppHandle, id: String) -> Result<(), String> {
+    if let Some(x) = app_handle.state::<Triggers>().0.lock().unwrap().get_mut(&id) {
+        x.clear_history();
+    }
+    Ok(())
+}
+
+
+// 设置告警规则，rules 为空时只清除规则，告警历史保留到断开连接
+pub async fn _set_alerts(app_handle: tauri::AppHandle, id: String, rules: Vec<AlertRule>) -> Result<()> {
+    let code = {
+        let msg_handles = app_handle.state::<MsgHandles>();
+        let msg_handles = msg_handles.0.lock().unwrap();
+        msg_handles.get(&id).context("未找到指定id")?.display_code()
+    };
+    let mut compiled = Vec::new();
+    for rule in rules {
+        let matcher = Matcher::new(&rule.query).with_context(|| format!("规则 {} 匹配条件错误", rule.name))?;
+        if matcher.matches_empty() {
+            return Err(anyhow::format_err!("规则 {} 匹配条件不能匹配空内容", rule.name));
+        }
+        compiled.push((rule, matcher));
+    }
+    let alerts = app_handle.state::<Alerts>();
+    let mut alerts = alerts.0.lock().unwrap();
+    match alerts.get_mut(&id) {
+        Some(x) => x.set_rules(compiled, code),
+        None => {
+            alerts.insert(id, AlertSet::new(compiled, code));
+        }
+    }
+    Ok(())
+}
+#[tauri::command]
+pub async fn set_alerts(app_handle: tauri::AppHandle, id: String, rules: Vec<AlertRule>) -> Result<(), String> {
+    catch_error_to_string!(_set_alerts, app_handle, id, rules)
+}
+
+#[tauri::command]
+pub async fn get_alert_history(app_handle: tauri::AppHandle, id: String) -> Result<Vec<AlertRecord>, String> {
+    Ok(app_handle.state::<Alerts>().0.lock().unwrap().get(&id).map(|v| v.history()).unwrap_or_default())
+}
+
+#[tauri::command]
+pub async fn clear_alert_history(app_handle: tauri::AppHandle, id: String) -> Result<(), String> {
+    if let Some(x) = app_handle.state::<Alerts>().0.lock().unwrap().get_mut(&id) {
+        x.clear_history();
+    }
+    Ok(())
+}
+
+
+// 开始从接收数据中解析绘图数据，重复调用时清空已有数据
+pub async fn _start_plot(app_handle: tauri::AppHandle, id: String, option: PlotOption) -> Result<()> {
+    let code = {
+        let msg_handles = app_handle.state::<MsgHandles>();
+        let msg_handles = msg_handles.0.lock().unwrap();
+        msg_handles.get(&id).context("未找到指定id")?.display_code()
+    };
+    let plotter = Plotter::new(option, code)?;
+    app_handle.state::<Plotters>().0.lock().unwrap().insert(id, plotter);
+    Ok(())
+}
+#[tauri::command]
+pub async fn start_plot(app_handle: tauri::AppHandle, id: String, option: Option<PlotOption>) -> Result<(), String> {
+    catch_error_to_string!(_start_plot, app_handle, id, option.unwrap_or_default())
+}
+
+// 停止解析绘图数据，已有数据保留到重新开始或断开连接，仍可查询与导出
+#[tauri::command]
+pub async fn stop_plot(app_handle: tauri::AppHandle, id: String) -> Result<bool, String> {
+    Ok(app_handle.state::<Plotters>().0.lock().unwrap().get_mut(&id).is_some_and(|v| v.stop()))
+}
+
+#[tauri::command]
+pub async fn get_plot_history(app_handle: tauri::AppHandle, id: String) -> Result<Vec<PlotPoint>, String> {
+    Ok(app_handle.state::<Plotters>().0.lock().unwrap().get(&id).map(|v| v.history()).unwrap_or_default())
+}
+
+// 导出绘图数据为 CSV，返回导出的行数
+// 先取出数据再写文件，避免写文件期间阻塞接收线程
+pub async fn _export_plot(app_handle: tauri::AppHandle, id: String, path: String) -> Result<usize> {
+    let (names, points) = {
+        let plotters = app_handle.state::<Plotters>();
+        let plotters = plotters.0.lock().unwrap();
+        let plotter = plotters.get(&id).context("未开始绘图")?;
+        (plotter.names(), plotter.history())
+    };
+    let file = File::create(&path).with_context(|| format!("创建文件失败：{path}"))?;
+    write_csv(&names, &points, &mut BufWriter::new(file))
+}
+#[tauri::command]
+pub async fn export_plot(app_handle: tauri::AppHandle, id: String, path: String) -> Result<usize, String> {
+    catch_error_to_string!(_export_plot, app_handle, id, path)
+}
+
+
+// 开启终端模拟，接收数据按会话显示编码解码、按 VT100/xterm 控制序列更新屏幕，变化通过 term_{id} 事件发送
+pub async fn _start_terminal(app_handle: tauri::AppHandle, id: String, option: TerminalOption) -> Result<TerminalSnapshot> {
+    let code = {
+        let msg_handles = app_handle.state::<MsgHandles>();
+        let msg_handles = msg_handles.0.lock().unwrap();
+        msg_handles.get(&id).context("未找到指定id")?.display_code()
+    };
+    let mut terminal = Terminal::new(&option, code);
+    let snapshot = terminal.snapshot();
+    app_handle.state::<Terminals>().0.lock().unwrap().insert(id, terminal);
+    Ok(snapshot)
+}
+#[tauri::command]
+pub async fn start_terminal(app_handle: tauri::AppHandle, id: String, option: Option<TerminalOption>) -> Result<TerminalSnapshot, String> {
+    catch_error_to_string!(_start_terminal, app_handle, id, option.unwrap_or_default())
+}
+
+#[tauri::command]
+pub async fn stop_terminal(app_handle: tauri::AppHandle, id: String) -> Result<bool, String> {
+    Ok(app_handle.state::<Terminals>().0.lock().unwrap().remove(&id).is_some())
+}
+
+pub async fn _resize_terminal(app_handle: tauri::AppHandle, id: String, cols: usize, rows: usize) -> Result<()> {
+    let terminals = app_handle.state::<Terminals>();
+    let mut terminals = terminals.0.lock().unwrap();
+    terminals.get_mut(&id).context("未开启终端模式")?.resize(cols, rows);
+    drop(terminals);
+    emit_terminal(&app_handle, &id);
+    Ok(())
+}
+#[tauri::command]
+pub async fn resize_terminal(app_handle: tauri::AppHandle, id: String, cols: usize, rows: usize) -> Result<(), String> {
+    catch_error_to_string!(_resize_terminal, app_handle, id, cols, rows)
+}
+
+pub async fn _get_terminal_snapshot(app_handle: tauri::AppHandle, id: String) -> Result<TerminalSnapshot> {
+    let terminals = app_handle.state::<Terminals>();
+    let mut terminals = terminals.0.lock().unwrap();
+    Ok(terminals.get_mut(&id).context("未开启终端模式")?.snapshot())
+}
+#[tauri::command]
+pub async fn get_terminal_snapshot(app_handle: tauri::AppHandle, id: String) -> Result<TerminalSnapshot, String> {
+    catch_error_to_string!(_get_terminal_snapshot, app_handle, id)
+}
+
+// 发送终端按键，按原始字节经发送事件写入串口，不做转义与换行处理
+pub async fn _send_key(app_handle: tauri::AppHandle, id: String, input: KeyInput) -> Result<()> {
+    let app_cursor = app_handle.state::<Terminals>().0.lock().unwrap()
+        .get(&id).map(|v| v.app_cursor()).context("未开启终端模式")?;
+    let code = app_handle.state::<MsgHandles>().0.lock().unwrap()
+        .get(&id).map(|v| v.send_code()).unwrap_or(encoding_rs::UTF_8);
+    let bytes = encode_key(&input, app_cursor, code);
+    if !bytes.is_empty() {
+        let payload = json!({ "type": "hex", "loop": false, "msg": bytes });
+        app_handle.trigger_global(&format!("send_{id}"), Some(payload.to_string()));
+    }
+    Ok(())
+}
+#[tauri::command]
+pub async fn send_key(app_handle: tauri::AppHandle, id: String, input: KeyInput) -> Result<(), String> {
+    catch_error_to_string!(_send_key, app_handle, id, input)
+}
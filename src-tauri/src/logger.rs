@@ -0,0 +1,431 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{channel, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use anyhow::{format_err, Context, Result};
+use chrono::{DateTime, Local};
+use encoding_rs::Encoding;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use crate::codec::{to_hex, StreamDecoder};
+
+
+// 数据方向
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Tx,
+    Rx,
+}
+
+impl Direction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Direction::Tx => "tx",
+            Direction::Rx => "rx",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Csv,
+    Jsonl,
+}
+
+pub const CSV_HEADER: &str = "time,ts,dir,hex,text";
+
+impl LogFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            LogFormat::Text => "log",
+            LogFormat::Csv => "csv",
+            LogFormat::Jsonl => "jsonl",
+        }
+    }
+}
+
+// 会话记录配置
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct LogOption {
+    pub format: LogFormat,
+    // 日志目录
+    pub dir: String,
+    // 文件名模板，支持 {port} {id} {time}，扩展名按格式自动添加
+    pub name: String,
+    // 单个文件大小上限，字节
+    pub max_size: Option<u64>,
+    // 单个文件记录时长上限，秒
+    pub max_time: Option<u64>,
+    // 保留的归档文件个数
+    pub keep: usize,
+    // 归档文件是否使用 gzip 压缩
+    pub compress: bool,
+}
+
+impl Default for LogOption {
+    fn default() -> Self {
+        Self {
+            format: LogFormat::Text,
+            dir: "logs".to_string(),
+            name: "{port}_{id}_{time}".to_string(),
+            max_size: None,
+            max_time: None,
+            keep: 10,
+            compress: false,
+        }
+    }
+}
+
+// 当前记录文件及归档文件
+#[derive(Clone, Debug, Serialize)]
+pub struct LogFiles {
+    pub path: String,
+    pub archives: Vec<String>,
+}
+
+// 会话记录器，逐条写入收发数据，超出大小或时长后轮转
+// 文本按方向分别流式解码，收发编码不同也能正确显示
+pub struct SessionLogger {
+    option: LogOption,
+    port: String,
+    id: String,
+    // 当前记录文件，与归档线程共享，清理归档时跳过
+    path: Arc<Mutex<PathBuf>>,
+    writer: BufWriter<File>,
+    // 当前文件已写入字节数
+    written: u64,
+    opened: Instant,
+    // 匹配同一模板生成的文件，包括之前会话的归档
+    pattern: Regex,
+    // 轮转后的文件交给归档线程压缩并清理，避免阻塞收发
+    archiver: Option<(Sender<PathBuf>, JoinHandle<()>)>,
+    // 归档线程的错误，下次写入时返回
+    archive_error: Arc<Mutex<Option<String>>>,
+    tx_decoder: StreamDecoder,
+    rx_decoder: StreamDecoder,
+}
+
+impl SessionLogger {
+    pub fn open(option: LogOption, port: &str, id: &str, tx_code: &'static Encoding, rx_code: &'static Encoding) -> Result<Self> {
+        fs::create_dir_all(&option.dir).with_context(|| format!("创建目录失败：{}", option.dir))?;
+        let path = file_path(&option, port, id);
+        let pattern = file_pattern(&option, port, id)?;
+        let (writer, written) = create_file(&path, option.format)?;
+        Ok(Self {
+            option,
+            port: port.to_string(),
+            id: id.to_string(),
+            path: Arc::new(Mutex::new(path)),
+            writer,
+            written,
+            opened: Instant::now(),
+            pattern,
+            archiver: None,
+            archive_error: Arc::new(Mutex::new(None)),
+            tx_decoder: StreamDecoder::new(tx_code),
+            rx_decoder: StreamDecoder::new(rx_code),
+        })
+    }
+
+    pub fn path(&self) -> String {
+        self.path.lock().unwrap().to_string_lossy().to_string()
+    }
+
+    // 归档文件为目录中同一模板的其他文件，从旧到新
+    pub fn files(&self) -> LogFiles {
+        let path = self.path.lock().unwrap().clone();
+        LogFiles {
+            path: path.to_string_lossy().to_string(),
+            archives: list_archives(&self.option.dir, &self.pattern, &path).iter().map(|v| v.to_string_lossy().to_string()).collect(),
+        }
+    }
+
+    // 每条记录写入后立即落盘，异常退出时也能保留日志
+    pub fn write(&mut self, time: DateTime<Local>, dir: Direction, data: &[u8]) -> Result<()> {
+        if let Some(e) = self.archive_error.lock().unwrap().take() {
+            return Err(format_err!(e));
+        }
+        let text = match dir {
+            Direction::Tx => self.tx_decoder.decode(data),
+            Direction::Rx => self.rx_decoder.decode(data),
+        };
+        let hex = to_hex(data);
+        let ts = time.timestamp_nanos_opt().unwrap_or_default();
+        let line = match self.option.format {
+            LogFormat::Text => format!(
+                "{} {} {} | {}\n",
+                time.format("%Y-%m-%d %H:%M:%S%.9f"), dir.as_str().to_uppercase(), hex, escape_control(&text),
+            ),
+            LogFormat::Csv => format!(
+                "{},{},{},{},{}\n",
+                time.to_rfc3339(), ts, dir.as_str(), hex, csv_field(&text),
+            ),
+            LogFormat::Jsonl => format!(
+                "{}\n",
+                json!({ "time": time.to_rfc3339(), "ts": ts, "dir": dir, "hex": hex, "text": text }),
+            ),
+        };
+
+        // 写入前检查，保证单个文件不超过大小上限
+        let over_size = self.option.max_size.is_some_and(|v| self.written > 0 && self.written + line.len() as u64 > v);
+        let over_time = self.option.max_time.is_some_and(|v| self.opened.elapsed() >= Duration::from_secs(v));
+        if over_size || over_time {
+            self.rotate()?;
+        }
+        self.writer.write_all(line.as_bytes())?;
+        self.writer.flush()?;
+        self.written += line.len() as u64;
+        Ok(())
+    }
+
+    // 关闭当前文件并交给归档线程
+    fn rotate(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        let path = file_path(&self.option, &self.port, &self.id);
+        let (writer, written) = create_file(&path, self.option.format)?;
+        self.writer = writer;
+        self.written = written;
+        self.opened = Instant::now();
+
+        let old = std::mem::replace(&mut *self.path.lock().unwrap(), path);
+        if self.archiver.is_none() {
+            self.archiver = Some(self.spawn_archiver());
+        }
+        if let Some((tx, _)) = &self.archiver {
+            tx.send(old).unwrap_or_default();
+        }
+        Ok(())
+    }
+
+    // 按顺序压缩轮转的文件，之后删除超出保留个数的最旧归档
+    fn spawn_archiver(&self) -> (Sender<PathBuf>, JoinHandle<()>) {
+        let (tx, rx) = channel::<PathBuf>();
+        let (option, pattern) = (self.option.clone(), self.pattern.clone());
+        let (current, error) = (self.path.clone(), self.archive_error.clone());
+        let handle = thread::spawn(move || {
+            for old in rx {
+                // 文件可能已被清理
+                if option.compress && old.exists() {
+                    if let Err(e) = compress(&old) {
+                        *error.lock().unwrap() = Some(e.to_string());
+                    }
+                }
+                let current = current.lock().unwrap().clone();
+                let archives = list_archives(&option.dir, &pattern, &current);
+                for v in &archives[..archives.len().saturating_sub(option.keep)] {
+                    fs::remove_file(v).unwrap_or_default();
+                }
+            }
+        });
+        (tx, handle)
+    }
+
+    // 结束记录，等待归档完成，返回记录文件及归档文件
+    pub fn finish(mut self) -> Result<LogFiles> {
+        self.writer.flush()?;
+        if let Some((tx, handle)) = self.archiver.take() {
+            drop(tx);
+            handle.join().unwrap_or_default();
+        }
+        if let Some(e) = self.archive_error.lock().unwrap().take() {
+            return Err(format_err!(e));
+        }
+        Ok(self.files())
+    }
+}
+
+// 按模板生成文件路径，重名时追加序号
+fn file_path(option: &LogOption, port: &str, id: &str) -> PathBuf {
+    // 端口名如 /dev/ttyUSB0 只取最后一段
+    let port = port.rsplit(['/', '\\']).next().unwrap_or(port);
+    let time = Local::now().format("%Y%m%d_%H%M%S").to_string();
+    let name = option.name.replace("{port}", port).replace("{id}", id).replace("{time}", &time)
+        .replace(['/', '\\', ':', '*', '?', '"', '<', '>', '|'], "_");
+    let ext = option.format.extension();
+    let dir = Path::new(&option.dir);
+    let mut path = dir.join(format!("{name}.{ext}"));
+    let mut n = 1;
+    while path.exists() || path.with_extension(format!("{ext}.gz")).exists() {
+        path = dir.join(format!("{name}_{n}.{ext}"));
+        n += 1;
+    }
+    path
+}
+
+// 同一模板生成的文件名，{time} 匹配任意时间，允许重名序号与 .gz 后缀
+fn file_pattern(option: &LogOption, port: &str, id: &str) -> Result<Regex> {
+    let port = port.rsplit(['/', '\\']).next().unwrap_or(port);
+    let name = option.name.split("{time}").map(|v| {
+        let v = v.replace("{port}", port).replace("{id}", id).replace(['/', '\\', ':', '*', '?', '"', '<', '>', '|'], "_");
+        regex::escape(&v)
+    }).collect::<Vec<String>>().join(r"\d{8}_\d{6}");
+    let ext = regex::escape(option.format.extension());
+    Ok(Regex::new(&format!(r"^{name}(_\d+)?\.{ext}(\.gz)?$"))?)
+}
+
+// 目录中匹配的文件，不含当前记录文件，按修改时间从旧到新
+fn list_archives(dir: &str, pattern: &Regex, current: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut files = entries.filter_map(|v| v.ok()).filter_map(|v| {
+        let path = v.path();
+        let name = path.file_name()?.to_str()?;
+        if !pattern.is_match(name) || path == current {
+            return None;
+        }
+        let modified = v.metadata().ok()?.modified().ok()?;
+        Some((modified, path))
+    }).collect::<Vec<_>>();
+    files.sort();
+    files.into_iter().map(|v| v.1).collect()
+}
+
+fn create_file(path: &Path, format: LogFormat) -> Result<(BufWriter<File>, u64)> {
+    let file = File::create(path).with_context(|| format!("创建文件失败：{}", path.display()))?;
+    let mut writer = BufWriter::new(file);
+    let mut written = 0;
+    if format == LogFormat::Csv {
+        writeln!(writer, "{CSV_HEADER}")?;
+        writer.flush()?;
+        written = CSV_HEADER.len() as u64 + 1;
+    }
+    Ok((writer, written))
+}
+
+// gzip 压缩后删除原文件，返回压缩文件路径
+fn compress(path: &Path) -> Result<PathBuf> {
+    let mut gz_path = path.as_os_str().to_owned();
+    gz_path.push(".gz");
+    let gz_path = PathBuf::from(gz_path);
+    let mut input = File::open(path)?;
+    let mut encoder = GzEncoder::new(File::create(&gz_path)?, Compression::default());
+    io::copy(&mut input, &mut encoder)?;
+    encoder.finish()?;
+    fs::remove_file(path)?;
+    Ok(gz_path)
+}
+
+// 文本格式一条记录占一行，控制字符转义显示
+fn escape_control(text: &str) -> String {
+    let mut s = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\r' => s.push_str("\\r"),
+            '\n' => s.push_str("\\n"),
+            '\t' => s.push_str("\\t"),
+            c if c.is_control() => s.push_str(&format!("\\x{:02X}", c as u32)),
+            c => s.push(c),
+        }
+    }
+    s
+}
+
+pub(crate) fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn temp_dir(name: &str) -> String {
+        let dir = std::env::temp_dir().join(name);
+        fs::remove_dir_all(&dir).unwrap_or_default();
+        dir.to_string_lossy().to_string()
+    }
+
+    fn write_log(name: &str, format: LogFormat) -> String {
+        let option = LogOption { format, dir: temp_dir(name), ..Default::default() };
+        let time = Local.timestamp_nanos(1_700_000_000_123_456_789);
+        let mut logger = SessionLogger::open(option, "COM1", "a", encoding_rs::UTF_8, encoding_rs::GBK).unwrap();
+        logger.write(time, Direction::Tx, b"AT,\"1\"\r\n").unwrap();
+        logger.write(time, Direction::Rx, &[0xB0, 0xA1]).unwrap();
+        let path = logger.finish().unwrap().path;
+        fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn test_log_formats() {
+        let text = write_log("multi_tools_log_text", LogFormat::Text);
+        let lines = text.lines().collect::<Vec<&str>>();
+        assert!(lines[0].ends_with(".123456789 TX 41 54 2C 22 31 22 0D 0A | AT,\"1\"\\r\\n"));
+        assert!(lines[1].ends_with(" RX B0 A1 | 啊"));
+
+        let csv = write_log("multi_tools_log_csv", LogFormat::Csv);
+        let lines = csv.lines().collect::<Vec<&str>>();
+        assert_eq!(lines[0], CSV_HEADER);
+        assert!(lines[1].contains(",1700000000123456789,tx,41 54 2C 22 31 22 0D 0A,\"AT,\"\"1\"\""));
+        assert_eq!(lines.len(), 4);
+
+        let jsonl = write_log("multi_tools_log_jsonl", LogFormat::Jsonl);
+        let v = jsonl.lines().map(|v| serde_json::from_str::<serde_json::Value>(v).unwrap()).collect::<Vec<_>>();
+        assert_eq!(v[0]["text"], "AT,\"1\"\r\n");
+        assert_eq!(v[1]["dir"], "rx");
+        assert_eq!(v[1]["ts"], 1_700_000_000_123_456_789i64);
+        assert_eq!(v[1]["hex"], "B0 A1");
+    }
+
+    #[test]
+    fn test_log_rotate() {
+        let dir = temp_dir("multi_tools_log_rotate");
+        let option = LogOption { dir: dir.clone(), name: "{port}_{id}".to_string(), max_size: Some(100), keep: 2, compress: true, ..Default::default() };
+        let mut logger = SessionLogger::open(option, "/dev/ttyUSB0", "s1", encoding_rs::UTF_8, encoding_rs::UTF_8).unwrap();
+        assert!(logger.path().ends_with("ttyUSB0_s1.log"));
+        // 每条记录约 60 字节，每个文件只能容纳一条
+        for v in 0..5u8 {
+            logger.write(Local::now(), Direction::Rx, &[0x30 + v; 4]).unwrap();
+        }
+        let files = logger.finish().unwrap();
+        let archives = files.archives;
+        assert_eq!(archives.len(), 2);
+        assert!(archives.iter().all(|v| v.ends_with(".log.gz")));
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 3);
+
+        // 最新归档解压后为第四条记录
+        let mut text = String::new();
+        io::Read::read_to_string(&mut flate2::read::GzDecoder::new(File::open(&archives[1]).unwrap()), &mut text).unwrap();
+        assert!(text.ends_with("RX 33 33 33 33 | 3333\n"));
+        assert!(fs::read_to_string(files.path).unwrap().ends_with("| 4444\n"));
+    }
+
+    #[test]
+    fn test_log_prune_old_sessions() {
+        let dir = temp_dir("multi_tools_log_prune");
+        fs::create_dir_all(&dir).unwrap();
+        // 之前会话留下的归档及无关文件
+        let old = std::time::SystemTime::now() - Duration::from_secs(3600);
+        for name in ["COM1_s1_20200101_000000.log.gz", "COM1_s1_20200101_000000_1.log", "COM2_s1_20200101_000000.log", "notes.txt"] {
+            File::create(Path::new(&dir).join(name)).unwrap().set_modified(old).unwrap();
+        }
+        let option = LogOption { dir: dir.clone(), max_size: Some(100), keep: 1, ..Default::default() };
+        let mut logger = SessionLogger::open(option, "COM1", "s1", encoding_rs::UTF_8, encoding_rs::UTF_8).unwrap();
+        for v in 0..2u8 {
+            logger.write(Local::now(), Direction::Rx, &[0x30 + v; 4]).unwrap();
+        }
+        let files = logger.finish().unwrap();
+        assert_eq!(files.archives.len(), 1);
+        let mut names = fs::read_dir(&dir).unwrap().map(|v| v.unwrap().file_name().to_string_lossy().to_string()).collect::<Vec<String>>();
+        names.sort();
+        assert_eq!(names.len(), 4);
+        assert!(names.contains(&"COM2_s1_20200101_000000.log".to_string()) && names.contains(&"notes.txt".to_string()));
+        assert!(!names.iter().any(|v| v.starts_with("COM1_s1_20200101")));
+    }
+}
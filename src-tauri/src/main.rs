@@ -1,13 +1,25 @@
 // Prevents additional console Index on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod alert;
+mod codec;
 mod command;
+mod logger;
 mod manage;
+mod parse;
+mod pcapng;
+mod plot;
+mod replay;
+mod search;
+mod stats;
+mod terminal;
+mod transfer;
+mod trigger;
 
 use tauri::Manager;
 use window_shadows::set_shadow;
-use crate::manage::{MsgHandles, SendHandles, Serials};
-use crate::command::{set_recv_setting, connect, disconnect, get_serial_ports};
+use crate::manage::{Captures, Loggers, MsgHandles, RecvTaps, Replays, SendHandles, Serials, Transfers, Triggers, Alerts, Plotters, Terminals};
+use crate::command::{set_recv_setting, connect, disconnect, get_serial_ports, send_file, cancel_send_file, modem_send, modem_recv, cancel_modem, parse_input, set_send_code, set_display_code, get_encodings, get_recv_snapshot, get_recv_text, set_time_format, set_line_option, set_newline_option, set_history_limit, get_history_stats, set_emit_option, query_history, start_log, stop_log, start_capture, stop_capture, export_pcapng, replay, pause_replay, resume_replay, cancel_replay, search_history, set_display_filter, set_triggers, get_trigger_history, clear_trigger_history, set_alerts, get_alert_history, clear_alert_history, start_plot, stop_plot, get_plot_history, export_plot, start_terminal, stop_terminal, resize_terminal, get_terminal_snapshot, send_key};
 
 fn main() {
 
@@ -15,11 +27,64 @@ fn main() {
         .manage(Serials::new())
         .manage(SendHandles::new())
         .manage(MsgHandles::new())
+        .manage(Transfers::new())
+        .manage(RecvTaps::new())
+        .manage(Loggers::new())
+        .manage(Captures::new())
+        .manage(Replays::new())
+        .manage(Triggers::new())
+        .manage(Alerts::new())
+        .manage(Plotters::new())
+        .manage(Terminals::new())
         .invoke_handler(tauri::generate_handler![
             connect,
             disconnect,
             get_serial_ports,
             set_recv_setting,
+            send_file,
+            cancel_send_file,
+            modem_send,
+            modem_recv,
+            cancel_modem,
+            parse_input,
+            set_send_code,
+            set_display_code,
+            get_encodings,
+            get_recv_snapshot,
+            get_recv_text,
+            set_time_format,
+            set_line_option,
+            set_newline_option,
+            set_history_limit,
+            get_history_stats,
+            set_emit_option,
+            query_history,
+            start_log,
+            stop_log,
+            start_capture,
+            stop_capture,
+            export_pcapng,
+            replay,
+            pause_replay,
+            resume_replay,
+            cancel_replay,
+            search_history,
+            set_display_filter,
+            set_triggers,
+            get_trigger_history,
+            clear_trigger_history,
+            set_alerts,
+            get_alert_history,
+            clear_alert_history,
+            start_plot,
+            stop_plot,
+            get_plot_history,
+            export_plot,
+            start_terminal,
+            stop_terminal,
+            resize_terminal,
+            get_terminal_snapshot,
+            send_key,
         ])
         .setup(|app| {
             let window = app.get_window("multi_tools").unwrap();
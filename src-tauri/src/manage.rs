@@ -1,8 +1,24 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
 use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
 use tauri::EventHandler;
 use multi_tools_serialport::sp::Serial;
-use anyhow::Result;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use crate::alert::AlertSet;
+use crate::codec::{Newline, NewlineNormalizer, StreamDecoder};
+use crate::logger::{Direction, SessionLogger};
+use crate::pcapng::PcapngWriter;
+use crate::plot::Plotter;
+use crate::search::{DisplayFilter, Matcher, SearchHit};
+use crate::stats::{SessionStats, StatsReport};
+use crate::terminal::Terminal;
+use crate::trigger::TriggerSet;
 
 
 // 串口句柄， key为串口UI实例ID
@@ -23,55 +39,444 @@ impl SendHandles {
     }
 }
 
+// 接收数据转发句柄，协议传输期间接收数据改发给传输线程， key为串口UI实例ID
+pub struct RecvTaps(pub Arc<Mutex<HashMap<String, Sender<Vec<u8>>>>>);
 
+impl RecvTaps {
+    pub fn new() -> Self {
+        Self(Arc::new(Mutex::new(HashMap::new())))
+    }
+}
 
-#[derive(Clone, Debug)]
-pub enum MsgCode {
-    GBK,
-    UTF8,
+// 传输取消标志（文件发送、协议传输）， key为串口UI实例ID
+pub struct Transfers(pub Arc<Mutex<HashMap<String, Arc<AtomicBool>>>>);
+
+impl Transfers {
+    pub fn new() -> Self {
+        Self(Arc::new(Mutex::new(HashMap::new())))
+    }
+
+    pub fn cancel(&self, id: &str) -> bool {
+        match self.0.lock().unwrap().get(id) {
+            None => false,
+            Some(x) => {
+                x.store(true, Ordering::Relaxed);
+                true
+            }
+        }
+    }
 }
 
-impl MsgCode {
-    pub fn to_code_string(&self, msg: &Vec<u8>) -> Result<String> {
-        Ok(
-            match self {
-                MsgCode::GBK => encoding_rs::GBK.decode(msg.as_slice()).0.to_string(),
-                MsgCode::UTF8 => String::from_utf8(msg.clone())?,
+// 回放暂停标志， key为串口UI实例ID，取消使用 Transfers
+pub struct Replays(pub Arc<Mutex<HashMap<String, Arc<AtomicBool>>>>);
+
+impl Replays {
+    pub fn new() -> Self {
+        Self(Arc::new(Mutex::new(HashMap::new())))
+    }
+
+    pub fn set_paused(&self, id: &str, paused: bool) -> bool {
+        match self.0.lock().unwrap().get(id) {
+            None => false,
+            Some(x) => {
+                x.store(paused, Ordering::Relaxed);
+                true
             }
-        )
+        }
+    }
+}
+
+
+// 会话记录器， key为串口UI实例ID
+pub struct Loggers(pub Arc<Mutex<HashMap<String, SessionLogger>>>);
+
+impl Loggers {
+    pub fn new() -> Self {
+        Self(Arc::new(Mutex::new(HashMap::new())))
+    }
+}
+
+// 自动应答规则， key为串口UI实例ID
+pub struct Triggers(pub Arc<Mutex<HashMap<String, TriggerSet>>>);
+
+impl Triggers {
+    pub fn new() -> Self {
+        Self(Arc::new(Mutex::new(HashMap::new())))
+    }
+}
+
+// 告警规则与历史， key为串口UI实例ID
+pub struct Alerts(pub Arc<Mutex<HashMap<String, AlertSet>>>);
+
+impl Alerts {
+    pub fn new() -> Self {
+        Self(Arc::new(Mutex::new(HashMap::new())))
+    }
+}
+
+// 绘图解析， key为串口UI实例ID
+pub struct Plotters(pub Arc<Mutex<HashMap<String, Plotter>>>);
+
+impl Plotters {
+    pub fn new() -> Self {
+        Self(Arc::new(Mutex::new(HashMap::new())))
     }
 }
 
+// 终端模拟， key为串口UI实例ID
+pub struct Terminals(pub Arc<Mutex<HashMap<String, Terminal>>>);
+
+impl Terminals {
+    pub fn new() -> Self {
+        Self(Arc::new(Mutex::new(HashMap::new())))
+    }
+}
+
+pub type CaptureWriter = PcapngWriter<BufWriter<File>>;
+
+// pcapng 实时抓包及下一条待写入记录的序号， key为串口UI实例ID
+pub struct Captures(pub Arc<Mutex<HashMap<String, (CaptureWriter, u64)>>>);
+
+impl Captures {
+    pub fn new() -> Self {
+        Self(Arc::new(Mutex::new(HashMap::new())))
+    }
+}
+
+
+// 显示编码，可为任意 encoding_rs 编码
 #[derive(Clone, Debug)]
-struct BufferTime {
+pub struct MsgCode(pub &'static encoding_rs::Encoding);
+
+// 收发记录，通过 recv_{id} 事件原样发往前端，不含任何标记
+// 设备数据只放在 data 与 text 中，前端显示时需按文本转义
+#[derive(Clone, Debug, Serialize)]
+pub struct RecvRecord {
+    pub seq: u64,
+    // 微秒时间戳
+    pub ts: i64,
+    // 距会话开始的单调时钟纳秒数，不受系统时间调整影响
+    pub mono: u64,
+    // 距上一条记录的纳秒数
+    pub delta: u64,
+    pub dir: Direction,
+    #[serde(rename = "data")]
     buffer: Vec<u8>,
-    time: String,
+    // 按显示编码流式解码后的文本
+    pub text: String,
+    #[serde(skip)]
+    time: DateTime<Local>,
 }
 
-#[derive(Clone, Debug)]
+// 显示时间格式
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeFormat {
+    // 时:分:秒.微秒
+    #[default]
+    Time,
+    // 带日期的绝对时间
+    Date,
+    // 距会话开始
+    Relative,
+    // 距上一条记录
+    Delta,
+}
+
+impl TimeFormat {
+    fn format(&self, v: &RecvRecord) -> String {
+        let elapsed = |ns: u64| format!("+{}.{:06}", ns / 1_000_000_000, ns % 1_000_000_000 / 1000);
+        match self {
+            TimeFormat::Time => v.time.format("%H:%M:%S%.6f").to_string(),
+            TimeFormat::Date => v.time.format("%Y-%m-%d %H:%M:%S%.6f").to_string(),
+            TimeFormat::Relative => elapsed(v.mono),
+            TimeFormat::Delta => elapsed(v.delta),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LineEnding {
+    Lf,
+    Cr,
+    CrLf,
+    // \n、\r\n 或单独的 \r
+    #[default]
+    Any,
+}
+
+impl LineEnding {
+    // 第一个完整行的结束位置（含换行符），行未结束时返回 None
+    fn find(&self, data: &[u8]) -> Option<usize> {
+        match self {
+            LineEnding::Lf => data.iter().position(|v| *v == b'\n').map(|v| v + 1),
+            LineEnding::Cr => data.iter().position(|v| *v == b'\r').map(|v| v + 1),
+            LineEnding::CrLf => data.windows(2).position(|v| v == b"\r\n").map(|v| v + 2),
+            LineEnding::Any => {
+                let i = data.iter().position(|v| *v == b'\n' || *v == b'\r')?;
+                match (data[i], data.get(i + 1)) {
+                    (b'\n', _) => Some(i + 1),
+                    (_, Some(b'\n')) => Some(i + 2),
+                    (_, Some(_)) => Some(i + 1),
+                    // 末尾的 \r 需等待下一个字节确定是否为 \r\n
+                    (_, None) => None,
+                }
+            }
+        }
+    }
+}
+
+// 按行接收，接收数据缓存到换行符后作为一条记录，时间为该行第一个字节的到达时间
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct LineOption {
+    pub ending: LineEnding,
+    // 超过该毫秒数仍未收到换行符时直接作为一条记录，0 表示一直等待
+    pub timeout: u64,
+    // 未结束的行达到该字节数时截断为一条记录，避免一直收不到换行符时无限缓存
+    pub max_len: usize,
+}
+
+impl Default for LineOption {
+    fn default() -> Self {
+        Self {
+            ending: LineEnding::default(),
+            timeout: 100,
+            max_len: 4096,
+        }
+    }
+}
+
+// 发送文本的换行处理
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum NewlineMode {
+    // 原样发送
+    #[default]
+    Keep,
+    // 末尾追加换行符
+    Append,
+    // \n、\r\n 与单独的 \r 统一替换为换行符
+    Translate,
+}
+
+// 会话换行设置，只影响发送的文本与接收区显示，日志与计数仍为原始字节
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct NewlineOption {
+    // 十六进制、按键与文件发送不受影响
+    pub send: NewlineMode,
+    pub send_newline: Newline,
+    // 接收显示时统一为该换行符，None 表示原样显示
+    pub display: Option<Newline>,
+}
+
+// 接收历史上限，超出后从最早的记录开始淘汰，None 表示不限制
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct HistoryLimit {
+    pub max_bytes: Option<usize>,
+    pub max_entries: Option<usize>,
+}
+
+impl Default for HistoryLimit {
+    fn default() -> Self {
+        Self {
+            max_bytes: Some(32 * 1024 * 1024),
+            max_entries: Some(100_000),
+        }
+    }
+}
+
+// 接收事件合并发送配置，满足任一条件即发送
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct EmitOption {
+    // 每秒最多发送次数，0 表示每次读取都发送
+    pub hz: u32,
+    // 累计未发送字节数达到该值时立即发送
+    pub max_bytes: usize,
+}
+
+impl Default for EmitOption {
+    fn default() -> Self {
+        Self {
+            hz: 30,
+            max_bytes: 64 * 1024,
+        }
+    }
+}
+
+// 接收线程中合并接收事件，数据本身已完整记录在历史中
+#[derive(Debug)]
+pub struct Coalescer {
+    option: EmitOption,
+    pending: usize,
+    last: Instant,
+}
+
+impl Coalescer {
+    pub fn new(option: EmitOption) -> Self {
+        Self { option, pending: 0, last: Instant::now() }
+    }
+
+    pub fn set_option(&mut self, option: &EmitOption) {
+        if &self.option != option {
+            self.option = option.clone();
+        }
+    }
+
+    // 记录新数据，返回是否需要立即发送
+    pub fn push(&mut self, len: usize) -> bool {
+        self.pending += len;
+        self.pending >= self.option.max_bytes || self.due()
+    }
+
+    // 有未发送数据且距上次发送已超过间隔
+    pub fn due(&self) -> bool {
+        self.pending > 0 && (self.option.hz == 0 || self.last.elapsed() >= Duration::from_secs(1) / self.option.hz)
+    }
+
+    pub fn reset(&mut self) {
+        self.pending = 0;
+        self.last = Instant::now();
+    }
+}
+
+// 连续发送合并为一条记录的长度上限
+const MERGE_LIMIT: usize = 4096;
+
+// 增量更新，前端先移除最早的 trim 条记录再追加 records，reset 为 true 时整体替换
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct RecvUpdate {
+    pub reset: bool,
+    pub trim: usize,
+    pub records: Vec<RecvRecord>,
+}
+
+// 分页查询的显示格式
+#[derive(Clone, Debug, Deserialize)]
+pub struct RowFormat {
+    pub hex: bool,
+    pub show_time: bool,
+}
+
+// 分页查询结果，offset 为第一条记录的序号，加上下标即为记录的绝对序号
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct HistoryPage {
+    pub total: usize,
+    pub offset: u64,
+    pub rows: Vec<String>,
+}
+
+// 接收历史统计
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct HistoryStats {
+    pub entries: usize,
+    pub bytes: usize,
+    pub dropped_entries: u64,
+    pub dropped_bytes: u64,
+    // 淘汰记录写入的磁盘文件
+    pub spill: Option<String>,
+}
+
+#[derive(Debug)]
 pub struct MsgHandle {
-    recv_buffer: Vec<BufferTime>,
+    recv_buffer: VecDeque<RecvRecord>,
+    recv_limit: HistoryLimit,
+    emit_option: EmitOption,
+    // 历史中的字节数
+    recv_bytes: usize,
+    dropped_entries: u64,
+    dropped_bytes: u64,
+    spill: Option<(String, BufWriter<File>)>,
+    display_filter: Option<DisplayFilter>,
     recv_show_time: bool,
+    time_format: TimeFormat,
+    // 会话开始时刻，记录的单调时间以此为起点
+    start: Instant,
+    // 上一条记录的单调时间
+    last_mono: u64,
     recv_hex: bool,
-    recv_code: MsgCode,
+    line_option: Option<LineOption>,
+    // 按行接收时尚未结束的行及其第一个字节的到达时间
+    line_pending: Vec<u8>,
+    line_start: Option<(DateTime<Local>, Instant)>,
+    // 是否在显示中穿插发送记录，以 >> 发送 / << 接收 标记方向
+    show_send: bool,
+    recv_decoder: StreamDecoder,
+    newline_option: NewlineOption,
+    recv_normalizer: NewlineNormalizer,
     recv_len: Option<u32>,
-    pub(crate) recv_count: u32,
-    pub(crate) send_count: u32,
+    send_code: &'static encoding_rs::Encoding,
+    port_name: String,
+    // 下一条记录的序号
+    next_seq: u64,
+    // 已发往前端的记录序号上限
+    emitted_seq: u64,
+    // 已写入抓包的记录序号上限，与 emitted_seq 之前的记录都不再合并
+    sealed_seq: u64,
+    // 尚未计入前端事件合并的发送字节数
+    pending_send: usize,
+    // 前端当前显示的记录数
+    shown: usize,
+    // 前端显示的第一条记录的序号，之后可见且已发送的记录都在前端
+    shown_first: u64,
+    // 已淘汰但前端仍在显示的记录数，下次更新时移除
+    evicted_shown: usize,
+    stats: SessionStats,
+    pub(crate) recv_count: u64,
+    pub(crate) send_count: u64,
 }
 
 impl MsgHandle {
     pub fn new() -> Self {
         Self {
-            recv_buffer: Vec::new(),
+            recv_buffer: VecDeque::new(),
+            recv_limit: HistoryLimit::default(),
+            emit_option: EmitOption::default(),
+            recv_bytes: 0,
+            dropped_entries: 0,
+            dropped_bytes: 0,
+            spill: None,
+            display_filter: None,
             recv_show_time: false,
+            time_format: TimeFormat::default(),
+            start: Instant::now(),
+            last_mono: 0,
             recv_hex: false,
-            recv_code: MsgCode::UTF8,
+            line_option: None,
+            line_pending: Vec::new(),
+            line_start: None,
+            show_send: false,
+            recv_decoder: StreamDecoder::new(encoding_rs::UTF_8),
+            newline_option: NewlineOption::default(),
+            recv_normalizer: NewlineNormalizer::new(None),
             recv_len: None,
+            send_code: encoding_rs::UTF_8,
+            port_name: String::new(),
+            next_seq: 0,
+            emitted_seq: 0,
+            sealed_seq: 0,
+            pending_send: 0,
+            shown: 0,
+            shown_first: 0,
+            evicted_shown: 0,
+            stats: SessionStats::new(Instant::now()),
             recv_count: 0,
             send_count: 0,
         }
     }
 
+    pub fn set_port_name(&mut self, name: &str) {
+        self.port_name = name.to_string();
+    }
+
+    pub fn port_name(&self) -> &str {
+        &self.port_name
+    }
+
     pub fn set_recv_len(&mut self, len: Option<u32>) {
         self.recv_len = len;
     }
@@ -80,12 +485,66 @@ impl MsgHandle {
         self.recv_show_time = is_show;
     }
 
+    pub fn set_time_format(&mut self, format: TimeFormat) {
+        self.time_format = format;
+    }
+
+    // 设置按行接收，None 时关闭，切换前先把未结束的行作为一条记录
+    pub fn set_line_option(&mut self, option: Option<LineOption>) {
+        self.flush_line(true);
+        self.line_option = option;
+    }
+
     pub fn set_display_hex(&mut self, is_hex: bool) {
         self.recv_hex = is_hex;
     }
 
+    pub fn set_display_send(&mut self, is_show: bool) {
+        self.show_send = is_show;
+    }
+
+    // 切换编码后按顺序重新解码全部历史，发送记录逐条独立解码
     pub fn set_display_code(&mut self, code: MsgCode) {
-        self.recv_code = code;
+        self.recv_decoder = StreamDecoder::new(code.0);
+        self.redecode();
+    }
+
+    // 设置换行处理，显示换行符变化时重新解码全部历史
+    pub fn set_newline_option(&mut self, option: NewlineOption) {
+        let redecode = option.display != self.newline_option.display;
+        self.newline_option = option;
+        if redecode {
+            self.recv_decoder = StreamDecoder::new(self.recv_decoder.encoding());
+            self.redecode();
+        }
+    }
+
+    pub fn newline_option(&self) -> &NewlineOption {
+        &self.newline_option
+    }
+
+    fn redecode(&mut self) {
+        let code = self.recv_decoder.encoding();
+        let display = self.newline_option.display;
+        self.recv_normalizer = NewlineNormalizer::new(display);
+        for v in self.recv_buffer.iter_mut() {
+            v.text = match v.dir {
+                Direction::Rx => self.recv_normalizer.normalize(self.recv_decoder.decode(&v.buffer)),
+                Direction::Tx => NewlineNormalizer::new(display).normalize(StreamDecoder::new(code).decode(&v.buffer)),
+            };
+        }
+    }
+
+    pub fn display_code(&self) -> &'static encoding_rs::Encoding {
+        self.recv_decoder.encoding()
+    }
+
+    pub fn set_send_code(&mut self, code: &'static encoding_rs::Encoding) {
+        self.send_code = code;
+    }
+
+    pub fn send_code(&self) -> &'static encoding_rs::Encoding {
+        self.send_code
     }
 
     pub fn clear_count(&mut self, is_recv: bool) {
@@ -96,53 +555,401 @@ impl MsgHandle {
         }
     }
 
+    pub fn set_history_limit(&mut self, limit: HistoryLimit) {
+        self.recv_limit = limit;
+        self.evict();
+    }
+
+    pub fn set_emit_option(&mut self, option: EmitOption) {
+        self.emit_option = option;
+    }
+
+    pub fn emit_option(&self) -> &EmitOption {
+        &self.emit_option
+    }
+
+    // 设置淘汰记录的落盘文件，追加写入，None 时直接丢弃
+    pub fn set_spill(&mut self, path: Option<String>) -> Result<()> {
+        if let Some((_, mut w)) = self.spill.take() {
+            w.flush()?;
+        }
+        if let Some(path) = path {
+            let file = OpenOptions::new().create(true).append(true).open(&path)
+                .with_context(|| format!("打开文件失败：{path}"))?;
+            self.spill = Some((path, BufWriter::new(file)));
+        }
+        Ok(())
+    }
+
+    pub fn history_stats(&self) -> HistoryStats {
+        HistoryStats {
+            entries: self.recv_buffer.len(),
+            bytes: self.recv_bytes,
+            dropped_entries: self.dropped_entries,
+            dropped_bytes: self.dropped_bytes,
+            spill: self.spill.as_ref().map(|v| v.0.clone()),
+        }
+    }
+
+    // 超出上限时淘汰最早的记录，至少保留最新一条
+    fn evict(&mut self) {
+        let HistoryLimit { max_bytes, max_entries } = self.recv_limit;
+        while self.recv_buffer.len() > 1
+            && (max_entries.is_some_and(|v| self.recv_buffer.len() > v) || max_bytes.is_some_and(|v| self.recv_bytes > v)) {
+            let v = self.recv_buffer.pop_front().unwrap();
+            if v.seq >= self.shown_first && v.seq < self.emitted_seq && self.visible(&v) {
+                self.shown = self.shown.saturating_sub(1);
+                self.evicted_shown += 1;
+            }
+            self.recv_bytes -= v.buffer.len();
+            self.dropped_entries += 1;
+            self.dropped_bytes += v.buffer.len() as u64;
+            if let Some((_, w)) = self.spill.as_mut() {
+                let hex = v.buffer.iter().map(|v| format!("{:02X}", v)).collect::<Vec<String>>().join(" ");
+                // 落盘失败不影响接收
+                writeln!(w, "[{}] {} {}", v.time.format("%Y-%m-%d %H:%M:%S%.6f"), v.dir.as_str(), hex).unwrap_or_default();
+            }
+        }
+        if let Some((_, w)) = self.spill.as_mut() {
+            w.flush().unwrap_or_default();
+        }
+    }
+
     pub fn clear_buffer(&mut self) {
         self.recv_buffer.clear();
+        self.recv_bytes = 0;
+        self.emitted_seq = self.next_seq;
+        self.shown = 0;
+        self.shown_first = self.next_seq;
+        self.evicted_shown = 0;
+        self.line_pending.clear();
+        self.line_start = None;
+        // 清空前未结束的多字节字符与 \r 不再影响之后的数据
+        self.recv_decoder = StreamDecoder::new(self.recv_decoder.encoding());
+        self.recv_normalizer = NewlineNormalizer::new(self.newline_option.display);
+    }
+
+    // 设置显示过滤，None 时显示全部，搜索与分页查询不受影响
+    pub fn set_display_filter(&mut self, filter: Option<DisplayFilter>) {
+        self.display_filter = filter;
+    }
+
+    fn visible(&self, v: &RecvRecord) -> bool {
+        if v.dir == Direction::Tx && !self.show_send {
+            return false;
+        }
+        match &self.display_filter {
+            None => true,
+            Some(f) => f.accept(&v.text, &v.buffer),
+        }
+    }
+
+    // 搜索历史，收发方向各自作为连续的数据流匹配，匹配可跨越多条记录，最多返回 max 个匹配
+    pub fn search(&self, matcher: &Matcher, max: usize) -> Vec<SearchHit> {
+        let mut hits = Vec::new();
+        for dir in [Direction::Rx, Direction::Tx] {
+            let records = self.recv_buffer.iter().filter(|v| v.dir == dir).collect::<Vec<&RecvRecord>>();
+            if records.is_empty() {
+                continue;
+            }
+            // 每条记录在连续内容中的起始位置，文本按 UTF-16 计，十六进制按字节计
+            let mut starts = Vec::with_capacity(records.len());
+            let (mut text, mut bytes, mut pos) = (String::new(), Vec::new(), 0);
+            for v in &records {
+                starts.push(pos);
+                match matcher {
+                    Matcher::Regex(_) => {
+                        text.push_str(&v.text);
+                        pos += v.text.encode_utf16().count();
+                    }
+                    Matcher::Hex(_) => {
+                        bytes.extend_from_slice(&v.buffer);
+                        pos += v.buffer.len();
+                    }
+                }
+            }
+            for (start, end) in matcher.find_all(&text, &bytes).into_iter().take(max) {
+                // 起点属于最后一个起始位置不大于 start 的记录，终点属于包含 end 前一个单位的记录
+                let i = starts.partition_point(|v| *v <= start) - 1;
+                let j = if end > start { starts.partition_point(|v| *v < end) - 1 } else { i };
+                hits.push(SearchHit { seq: records[i].seq, start: start - starts[i], end_seq: records[j].seq, end: end - starts[j] });
+            }
+        }
+        hits.sort_by_key(|v| (v.seq, v.start));
+        hits.truncate(max);
+        hits
+    }
+
+    pub fn stats_mut(&mut self) -> &mut SessionStats {
+        &mut self.stats
+    }
+
+    // 生成统计报告，两次调用之间为一个速率统计周期
+    pub fn stats_report(&mut self) -> StatsReport {
+        self.stats.report(Instant::now())
+    }
+
+    pub fn seq(&self) -> u64 {
+        self.next_seq
     }
 
     pub fn add_buffer(&mut self, buffer: Vec<u8>){
-        self.recv_count += buffer.len() as u32;
-        self.recv_buffer.push(BufferTime{
+        self.recv_count += buffer.len() as u64;
+        self.stats.add_bytes(Direction::Rx, buffer.len());
+        let now = (Local::now(), Instant::now());
+        let Some(option) = &self.line_option else {
+            self.push_recv(buffer, now);
+            return;
+        };
+        let ending = option.ending;
+        let max_len = option.max_len.max(1);
+        if self.line_pending.is_empty() {
+            self.line_start = Some(now);
+        }
+        self.line_pending.extend(buffer);
+        while let Some(end) = ending.find(&self.line_pending).or((self.line_pending.len() >= max_len).then_some(max_len)) {
+            let line = self.line_pending.drain(..end.min(max_len)).collect::<Vec<u8>>();
+            let start = self.line_start.take().unwrap_or(now);
+            self.push_recv(line, start);
+            // 之前未结束的行已完整，剩余数据都来自本次读取
+            if !self.line_pending.is_empty() {
+                self.line_start = Some(now);
+            }
+        }
+    }
+
+    // 未结束的行超时后作为一条记录，force 为 true 时不等待超时，返回写入的字节数
+    pub fn flush_line(&mut self, force: bool) -> usize {
+        let Some(start) = self.line_start else {
+            return 0;
+        };
+        let timeout = self.line_option.as_ref().map(|v| v.timeout).unwrap_or_default();
+        if !force && (timeout == 0 || start.1.elapsed() < Duration::from_millis(timeout)) {
+            return 0;
+        }
+        self.line_start = None;
+        let line = std::mem::take(&mut self.line_pending);
+        let len = line.len();
+        self.push_recv(line, start);
+        len
+    }
+
+    fn push_recv(&mut self, buffer: Vec<u8>, at: (DateTime<Local>, Instant)) {
+        let text = self.recv_normalizer.normalize(self.recv_decoder.decode(&buffer));
+        self.push(Direction::Rx, buffer, text, at);
+    }
+
+    // 记录发送数据，与接收记录按时间顺序存放在同一历史中
+    pub fn add_send(&mut self, buffer: &[u8]) {
+        self.send_count += buffer.len() as u64;
+        self.pending_send += buffer.len();
+        self.stats.add_bytes(Direction::Tx, buffer.len());
+        let text = self.send_text(buffer);
+        self.push(Direction::Tx, buffer.to_vec(), text, (Local::now(), Instant::now()));
+    }
+
+    // 记录分块发送的数据，连续的块在发往前端或写入抓包前合并为一条记录
+    pub fn add_send_chunk(&mut self, buffer: &[u8]) {
+        let sealed = self.emitted_seq.max(self.sealed_seq);
+        let Some(last) = self.recv_buffer.back_mut() else {
+            return self.add_send(buffer);
+        };
+        if last.dir != Direction::Tx || last.seq < sealed || last.buffer.len() + buffer.len() > MERGE_LIMIT {
+            return self.add_send(buffer);
+        }
+        last.buffer.extend_from_slice(buffer);
+        let data = std::mem::take(&mut last.buffer);
+        let text = self.send_text(&data);
+        let last = self.recv_buffer.back_mut().unwrap();
+        last.buffer = data;
+        last.text = text;
+        self.send_count += buffer.len() as u64;
+        self.pending_send += buffer.len();
+        self.stats.add_bytes(Direction::Tx, buffer.len());
+        self.recv_bytes += buffer.len();
+        self.evict();
+    }
+
+    fn send_text(&self, buffer: &[u8]) -> String {
+        let text = StreamDecoder::new(self.recv_decoder.encoding()).decode(buffer);
+        NewlineNormalizer::new(self.newline_option.display).normalize(text)
+    }
+
+    // 取出上次调用后新增的发送字节数，由接收线程计入前端事件合并
+    pub fn take_pending_send(&mut self) -> usize {
+        std::mem::take(&mut self.pending_send)
+    }
+
+    // 当前记录已写入抓包，之后的发送不再合并到这些记录
+    pub fn seal(&mut self) {
+        self.sealed_seq = self.next_seq;
+    }
+
+    // at 为 (系统时间, 单调时刻)
+    fn push(&mut self, dir: Direction, buffer: Vec<u8>, text: String, at: (DateTime<Local>, Instant)) {
+        let (time, instant) = at;
+        let mono = instant.duration_since(self.start).as_nanos() as u64;
+        // 按行接收时行的时间可能早于之前的发送记录
+        let delta = mono.saturating_sub(self.last_mono);
+        self.last_mono = self.last_mono.max(mono);
+        self.stats.add_frame(dir);
+        self.recv_bytes += buffer.len();
+        self.recv_buffer.push_back(RecvRecord{
+            seq: self.next_seq,
+            ts: time.timestamp_micros(),
+            mono,
+            delta,
+            dir,
             buffer,
-            time: format!("{}", chrono::Local::now().format("%H:%M:%S%.6f")),
+            text,
+            time,
         });
+        self.next_seq += 1;
+        self.evict();
     }
 
-    pub fn add_send_count(&mut self, buffer: &Vec<u8>) {
-        self.send_count += buffer.len() as u32;
+    // 当前显示范围内的记录，已应用显示过滤与显示条数限制
+    fn visible_records(&self) -> Vec<&RecvRecord> {
+        let buffer = self.recv_buffer.iter().filter(|v| self.visible(v)).collect::<Vec<&RecvRecord>>();
+        let s = match self.recv_len {
+            Some(len) => buffer.len().saturating_sub(len as usize),
+            None => 0,
+        };
+        buffer[s..].to_vec()
     }
 
+    // 按当前显示设置渲染为纯文本，用于复制当前显示内容
     pub fn recv_buffer_to_string(&self) -> String {
-        let len = self.recv_len;
-        let e = self.recv_buffer.len().clone();
-        let s = if len.is_none() {
-            0
+        self.visible_records().into_iter().map(|v| self.render(v)).collect::<Vec<String>>().join(self.separator())
+    }
+
+    // 记录之间的分隔符，仅十六进制且不显示时间、不穿插发送时用空格分隔
+    fn separator(&self) -> &'static str {
+        if self.recv_hex && !self.recv_show_time && !self.show_send { " " } else { "" }
+    }
+
+    fn render(&self, v: &RecvRecord) -> String {
+        self.render_with(v, self.recv_show_time, self.recv_hex)
+    }
+
+    // 穿插显示发送时每条记录单独一行，并标记方向
+    fn render_with(&self, v: &RecvRecord, show_time: bool, hex: bool) -> String {
+        let time = self.time_format.format(v);
+        if self.show_send {
+            let mark = match v.dir {
+                Direction::Tx => ">>",
+                Direction::Rx => "<<",
+            };
+            let data = if hex { v.buffer.iter().map(|v| format!("{:02X}", v)).collect::<Vec<String>>().join(" ") } else { v.text.clone() };
+            let data = data.trim_end_matches(['\r', '\n']);
+            return if show_time {
+                format!("[{time}] {mark} {data}\r\n")
+            } else {
+                format!("{mark} {data}\r\n")
+            };
+        }
+        match [show_time, hex] {
+            [true, true] => {
+                format!("[{time}]: ") + &v.buffer.iter().map(|v| format!("{:02X}", v)).collect::<Vec<String>>().join(" ") + "\r\n"
+            },
+            [true, false] => {
+                format!("[{time}]: ") + &v.text + "\r\n"
+            },
+            [false, true] => {
+                v.buffer.iter().map(|v| format!("{:02X}", v)).collect::<Vec<String>>().join(" ")
+            },
+            [false, false] => {
+                v.text.clone()
+            },
+        }
+    }
+
+    // 取出尚未发往前端的记录，限制了显示条数时附带前端需移除的记录数
+    // 淘汰的记录同样计入 trim，前端显示的记录数不会超过历史
+    pub fn take_update(&mut self) -> RecvUpdate {
+        let new = (self.next_seq - self.emitted_seq) as usize;
+        self.emitted_seq = self.next_seq;
+        let evicted = std::mem::take(&mut self.evicted_shown);
+        if new == 0 {
+            return RecvUpdate { trim: evicted, ..Default::default() };
+        }
+        // 未发送的记录已被淘汰时整体替换
+        if new > self.recv_buffer.len() {
+            return RecvUpdate { reset: true, trim: 0, records: self.shown_records() };
+        }
+        let s = self.recv_buffer.len() - new;
+        let mut records = self.recv_buffer.range(s..).filter(|v| self.visible(v)).cloned().collect::<Vec<RecvRecord>>();
+        let mut trim = 0;
+        if let Some(len) = self.recv_len {
+            let len = len as usize;
+            records.drain(..records.len().saturating_sub(len));
+            trim = (self.shown + records.len()).saturating_sub(len);
+        }
+        if trim >= self.shown {
+            self.shown_first = records.first().map(|v| v.seq).unwrap_or(self.next_seq);
         } else {
-            let len = len.unwrap() as usize;
-            if e < len { 0 } else { e - len }
-        };
+            self.skip_shown(trim);
+        }
+        self.shown = self.shown + records.len() - trim;
+        RecvUpdate { reset: false, trim: trim + evicted, records }
+    }
 
-        let buffer = &self.recv_buffer[s..e];
-
-        let show_time = self.recv_show_time;
-        let hex = self.recv_hex;
-        let buffer = buffer.iter().map(|v| {
-            match [show_time, hex] {
-                [true, true] => {
-                    format!("<strong>[{}]</strong>",v.time.clone()) + ": " + &v.buffer.iter().map(|v| format!("{:02X}", v)).collect::<Vec<String>>().join(" ") + "\r\n"
-                },
-                [true, false] => {
-                    format!("<strong>[{}]</strong>",v.time.clone()) + ": " + &self.recv_code.to_code_string(&v.buffer).unwrap_or_default() + "\r\n"
-                },
-                [false, true] => {
-                    v.buffer.iter().map(|v| format!("{:02X}", v)).collect::<Vec<String>>().join(" ")
-                },
-                [false, false] => {
-                    self.recv_code.to_code_string(&v.buffer).unwrap_or_default()
-                },
+    // 前端移除最早的 n 条记录后，前移 shown_first
+    fn skip_shown(&mut self, n: usize) {
+        let mut i = self.shown_first.saturating_sub(self.first_seq()) as usize;
+        let mut count = 0;
+        while count < n && i < self.recv_buffer.len() {
+            if self.visible(&self.recv_buffer[i]) {
+                count += 1;
             }
-        }).collect::<Vec<String>>();
-        buffer.join(if hex && !show_time { " " } else { "" })
+            i += 1;
+        }
+        self.shown_first = self.first_seq() + i as u64;
+    }
+
+    // 按当前显示设置取出前端应显示的全部记录
+    fn shown_records(&mut self) -> Vec<RecvRecord> {
+        let records = self.visible_records().into_iter().cloned().collect::<Vec<RecvRecord>>();
+        self.shown = records.len();
+        self.shown_first = records.first().map(|v| v.seq).unwrap_or(self.next_seq);
+        self.evicted_shown = 0;
+        records
+    }
+
+    // 历史中第一条记录的序号，淘汰与清空后仍满足 序号 = first_seq + 下标
+    fn first_seq(&self) -> u64 {
+        self.next_seq - self.recv_buffer.len() as u64
+    }
+
+    // 分页查询历史，start 为当前历史中的下标，格式缺省时使用会话显示设置
+    // 查询范围包含发送记录，搜索结果的序号减去 offset 即为下标
+    pub fn query(&self, start: usize, count: usize, format: Option<RowFormat>) -> HistoryPage {
+        let RowFormat { hex, show_time } = format.unwrap_or(RowFormat { hex: self.recv_hex, show_time: self.recv_show_time });
+        let total = self.recv_buffer.len();
+        let start = start.min(total);
+        let end = start.saturating_add(count).min(total);
+        HistoryPage {
+            total,
+            offset: self.first_seq(),
+            rows: self.recv_buffer.range(start..end).map(|v| self.render_with(v, show_time, hex)).collect(),
+        }
+    }
+
+    // 序号不小于 seq 的记录，返回 (序号, 时间, 方向, 数据)
+    pub fn records_from(&self, seq: u64) -> impl Iterator<Item = (u64, DateTime<Local>, Direction, &[u8])> {
+        let skip = (seq.saturating_sub(self.first_seq()) as usize).min(self.recv_buffer.len());
+        self.recv_buffer.range(skip..).map(|v| (v.seq, v.time, v.dir, v.buffer.as_slice()))
+    }
+
+    // 按时间顺序遍历历史中的收发记录
+    pub fn entries(&self) -> impl Iterator<Item = (DateTime<Local>, Direction, &[u8])> {
+        self.recv_buffer.iter().map(|v| (v.time, v.dir, v.buffer.as_slice()))
+    }
+
+    // 按当前显示设置取出完整内容，之后的增量从此处开始
+    pub fn snapshot(&mut self) -> Vec<RecvRecord> {
+        self.emitted_seq = self.next_seq;
+        self.shown_records()
     }
 }
 
@@ -160,10 +967,26 @@ impl MsgHandles {
 mod test {
     use super::*;
     use std::thread;
+    use crate::search::{SearchKind, SearchQuery};
 
     const BUFFER_UTF8: [u8;6] = [0xE5, 0x95, 0x8A, 0xE5, 0x95, 0x8A];
     const BUFFER_GBK: [u8;4] = [0xB0, 0xA1, 0xB0, 0xA1];
 
+    // 按会话显示设置把记录渲染为文本，便于比较
+    fn text(handle: &MsgHandle, records: &[RecvRecord]) -> String {
+        records.iter().map(|v| handle.render(v)).collect::<Vec<String>>().join(handle.separator())
+    }
+
+    fn update(handle: &mut MsgHandle) -> (bool, String) {
+        let update = handle.take_update();
+        (update.reset, text(handle, &update.records))
+    }
+
+    fn snapshot(handle: &mut MsgHandle) -> String {
+        let records = handle.snapshot();
+        text(handle, &records)
+    }
+
     #[test]
     fn test_add_buffer() {
         let mut handle = MsgHandle::new();
@@ -191,11 +1014,348 @@ mod test {
         dbg!(&handle.recv_buffer_to_string());
 
         handle.set_display_hex(false);
-        handle.set_display_code(MsgCode::GBK);
+        handle.set_display_code(MsgCode(encoding_rs::GBK));
         dbg!(&handle.recv_buffer_to_string());
 
     }
 
+    #[test]
+    fn test_display_code() {
+        let mut handle = MsgHandle::new();
+        handle.add_buffer(vec![0x4A, 0x55, 0x2D, 0x4E]);
+        handle.set_display_code(MsgCode(encoding_rs::UTF_16LE));
+        assert_eq!(handle.recv_buffer_to_string(), "啊中");
+        handle.clear_buffer();
+        handle.add_buffer(vec![0x82, 0xA0]);
+        handle.set_display_code(MsgCode(encoding_rs::SHIFT_JIS));
+        assert_eq!(handle.recv_buffer_to_string(), "あ");
+    }
+
+    #[test]
+    fn test_take_update() {
+        let mut handle = MsgHandle::new();
+        handle.set_display_hex(true);
+        handle.add_buffer(vec![0x01]);
+        handle.add_buffer(vec![0x02]);
+        assert_eq!(update(&mut handle), (false, "01 02".to_string()));
+        assert_eq!(update(&mut handle), (false, String::new()));
+        handle.add_buffer(vec![0x03, 0x04]);
+        assert_eq!(update(&mut handle), (false, "03 04".to_string()));
+        assert_eq!(handle.seq(), 3);
+
+        // 限制显示条数时只发送新增记录和需移除的条数
+        handle.set_recv_len(Some(2));
+        assert_eq!(snapshot(&mut handle), "02 03 04");
+        handle.add_buffer(vec![0x05]);
+        let v = handle.take_update();
+        assert_eq!((v.reset, v.trim, text(&handle, &v.records)), (false, 1, "05".to_string()));
+        for v in 0x06..0x09 {
+            handle.add_buffer(vec![v]);
+        }
+        let v = handle.take_update();
+        assert_eq!((v.reset, v.trim, text(&handle, &v.records)), (false, 2, "07 08".to_string()));
+
+        handle.clear_buffer();
+        handle.add_buffer(vec![0x09]);
+        assert_eq!(update(&mut handle), (false, "09".to_string()));
+        handle.add_buffer(vec![0x0A, 0x0B]);
+        assert_eq!(handle.take_update().trim, 0);
+        handle.add_buffer(vec![0x0C]);
+        assert_eq!(handle.take_update().trim, 1);
+        assert_eq!(snapshot(&mut handle), "0A 0B 0C");
+        assert_eq!(update(&mut handle), (false, String::new()));
+    }
+
+    #[test]
+    fn test_take_update_evict() {
+        // 不限制显示条数时，淘汰的记录也从前端移除
+        let mut handle = MsgHandle::new();
+        handle.set_display_hex(true);
+        handle.set_history_limit(HistoryLimit { max_bytes: None, max_entries: Some(3) });
+        handle.add_buffer(vec![0x01]);
+        handle.add_buffer(vec![0x02]);
+        assert_eq!(update(&mut handle), (false, "01 02".to_string()));
+        handle.add_buffer(vec![0x03]);
+        handle.add_buffer(vec![0x04]);
+        let v = handle.take_update();
+        assert_eq!((v.reset, v.trim, text(&handle, &v.records)), (false, 1, "03 04".to_string()));
+        handle.add_send(b"A");
+        handle.add_buffer(vec![0x05]);
+        let v = handle.take_update();
+        assert_eq!((v.trim, text(&handle, &v.records)), (2, "05".to_string()));
+        handle.add_buffer(vec![0x06]);
+        assert_eq!(handle.take_update().trim, 1);
+
+        // 同时限制显示条数，未显示的发送记录与已移除的记录被淘汰时不计入
+        handle.set_recv_len(Some(2));
+        assert_eq!(snapshot(&mut handle), "05 06");
+        handle.add_buffer(vec![0x07]);
+        handle.add_buffer(vec![0x08]);
+        let v = handle.take_update();
+        assert_eq!((v.trim, text(&handle, &v.records)), (2, "07 08".to_string()));
+        handle.set_history_limit(HistoryLimit { max_bytes: None, max_entries: Some(1) });
+        assert_eq!(handle.take_update().trim, 1);
+    }
+
+    #[test]
+    fn test_history_limit() {
+        let spill = std::env::temp_dir().join("multi_tools_history_spill.log");
+        std::fs::remove_file(&spill).unwrap_or_default();
+        let mut handle = MsgHandle::new();
+        handle.set_spill(Some(spill.to_string_lossy().to_string())).unwrap();
+        handle.set_history_limit(HistoryLimit { max_bytes: Some(4), max_entries: Some(3) });
+        for v in 0..5u8 {
+            handle.add_buffer(vec![v]);
+        }
+        assert_eq!(handle.history_stats().entries, 3);
+        assert_eq!(handle.history_stats().dropped_entries, 2);
+        handle.add_buffer(vec![5, 6, 7]);
+        let stats = handle.history_stats();
+        assert_eq!((stats.entries, stats.bytes, stats.dropped_entries, stats.dropped_bytes), (2, 4, 4, 4));
+        // 淘汰的记录按顺序写入文件
+        let log = std::fs::read_to_string(&spill).unwrap();
+        assert_eq!(log.lines().map(|v| v.rsplit(' ').next().unwrap()).collect::<Vec<&str>>(), vec!["00", "01", "02", "03"]);
+
+        handle.set_display_hex(true);
+        assert_eq!(update(&mut handle), (true, "04 05 06 07".to_string()));
+    }
+
+    #[test]
+    fn test_coalescer() {
+        let mut c = Coalescer::new(EmitOption { hz: 20, max_bytes: 100 });
+        c.reset();
+        assert!(!c.due());
+        assert!(!c.push(10));
+        assert!(c.push(90));
+        c.reset();
+        assert!(!c.push(1));
+        thread::sleep(Duration::from_millis(60));
+        assert!(c.due());
+        c.set_option(&EmitOption { hz: 0, max_bytes: 100 });
+        c.reset();
+        assert!(c.push(1));
+    }
+
+    #[test]
+    fn test_query() {
+        let mut handle = MsgHandle::new();
+        handle.set_history_limit(HistoryLimit { max_bytes: None, max_entries: Some(4) });
+        for v in 0..6u8 {
+            handle.add_buffer(vec![0x30 + v]);
+        }
+        assert_eq!(handle.query(1, 2, None), HistoryPage { total: 4, offset: 2, rows: vec!["3".to_string(), "4".to_string()] });
+        let page = handle.query(3, 10, Some(RowFormat { hex: true, show_time: false }));
+        assert_eq!(page.rows, vec!["35".to_string()]);
+        assert!(handle.query(9, 10, None).rows.is_empty());
+        assert!(handle.query(0, 1, Some(RowFormat { hex: false, show_time: true })).rows[0].ends_with(": 2\r\n"));
+        assert_eq!(handle.records_from(4).map(|v| (v.0, v.3.to_vec())).collect::<Vec<_>>(), vec![(4, vec![0x34]), (5, vec![0x35])]);
+        assert_eq!(handle.records_from(0).count(), 4);
+        // 清空后偏移仍为绝对序号
+        handle.clear_buffer();
+        handle.add_buffer(vec![0x36]);
+        assert_eq!(handle.query(0, 1, None), HistoryPage { total: 1, offset: 6, rows: vec!["6".to_string()] });
+    }
+
+    #[test]
+    fn test_display_filter() {
+        let mut handle = MsgHandle::new();
+        for v in ["OK\r\n", "ERROR\r\n", "OK\r\n"] {
+            handle.add_buffer(v.as_bytes().to_vec());
+        }
+        let matcher = Matcher::new(&SearchQuery { kind: SearchKind::Text, pattern: "ok".to_string(), ignore_case: true }).unwrap();
+        assert_eq!(handle.search(&matcher, 10), vec![
+            SearchHit { seq: 0, start: 0, end_seq: 0, end: 2 },
+            SearchHit { seq: 2, start: 0, end_seq: 2, end: 2 },
+        ]);
+        assert_eq!(handle.search(&matcher, 1).len(), 1);
+
+        handle.set_display_filter(Some(DisplayFilter { matcher: matcher.clone(), invert: true }));
+        assert_eq!(snapshot(&mut handle), "ERROR\r\n");
+        handle.add_buffer(b"OK".to_vec());
+        assert_eq!(update(&mut handle), (false, String::new()));
+        handle.set_display_filter(Some(DisplayFilter { matcher, invert: false }));
+        handle.add_buffer(b"ok!".to_vec());
+        assert_eq!(update(&mut handle), (false, "ok!".to_string()));
+        assert_eq!(snapshot(&mut handle), "OK\r\nOK\r\nOKok!");
+    }
+
+    #[test]
+    fn test_search_across_records() {
+        let mut handle = MsgHandle::new();
+        handle.add_buffer(b"x".to_vec());
+        handle.clear_buffer();
+        // 匹配内容被拆分到多次读取中，中间穿插发送记录
+        handle.add_buffer("中E".as_bytes().to_vec());
+        handle.add_send(b"ERR");
+        handle.add_buffer(b"RR".to_vec());
+        handle.add_buffer(b"OR\r\n".to_vec());
+        let matcher = Matcher::new(&SearchQuery { kind: SearchKind::Text, pattern: "error".to_string(), ignore_case: true }).unwrap();
+        assert_eq!(handle.search(&matcher, 10), vec![SearchHit { seq: 1, start: 1, end_seq: 4, end: 2 }]);
+        let matcher = Matcher::new(&SearchQuery { kind: SearchKind::Hex, pattern: "52 0D".to_string(), ignore_case: false }).unwrap();
+        assert_eq!(handle.search(&matcher, 10), vec![SearchHit { seq: 4, start: 1, end_seq: 4, end: 3 }]);
+        let matcher = Matcher::new(&SearchQuery { kind: SearchKind::Regex, pattern: "R+".to_string(), ignore_case: false }).unwrap();
+        assert_eq!(handle.search(&matcher, 2), vec![
+            SearchHit { seq: 2, start: 1, end_seq: 2, end: 3 },
+            SearchHit { seq: 3, start: 0, end_seq: 3, end: 2 },
+        ]);
+    }
+
+    #[test]
+    fn test_transcript() {
+        let mut handle = MsgHandle::new();
+        handle.add_send(b"AT\r\n");
+        handle.add_buffer(b"OK\r\n".to_vec());
+        assert_eq!((handle.send_count, handle.recv_count), (4, 4));
+        // 默认只显示接收
+        assert_eq!(snapshot(&mut handle), "OK\r\n");
+        handle.set_display_send(true);
+        assert_eq!(snapshot(&mut handle), ">> AT\r\n<< OK\r\n");
+        handle.add_send(&[0x01]);
+        handle.set_display_hex(true);
+        assert_eq!(update(&mut handle), (false, ">> 01\r\n".to_string()));
+        assert_eq!(handle.entries().map(|v| v.1).collect::<Vec<_>>(), vec![Direction::Tx, Direction::Rx, Direction::Tx]);
+    }
+
+    #[test]
+    fn test_send_chunk() {
+        let mut handle = MsgHandle::new();
+        handle.set_display_send(true);
+        handle.add_buffer(b"OK".to_vec());
+        handle.add_send_chunk("中".as_bytes()[..1].as_ref());
+        handle.add_send_chunk("中".as_bytes()[1..].as_ref());
+        handle.add_send_chunk(b"AB");
+        assert_eq!((handle.send_count, handle.take_pending_send(), handle.take_pending_send()), (5, 5, 0));
+        assert_eq!(handle.entries().map(|v| (v.1, v.2.len())).collect::<Vec<_>>(), vec![(Direction::Rx, 2), (Direction::Tx, 5)]);
+        let records = handle.take_update().records;
+        assert_eq!(records[1].text, "中AB");
+
+        // 已发往前端或已抓包的记录不再合并
+        handle.add_send_chunk(b"C");
+        handle.seal();
+        handle.add_send_chunk(b"D");
+        handle.add_send_chunk(&[0; MERGE_LIMIT]);
+        assert_eq!(handle.entries().map(|v| v.2.len()).collect::<Vec<_>>(), vec![2, 5, 1, 1, MERGE_LIMIT]);
+    }
+
+    #[test]
+    fn test_records() {
+        let mut handle = MsgHandle::new();
+        handle.add_buffer(b"<img src=x>".to_vec());
+        handle.add_send(b"AT");
+        handle.set_display_send(true);
+        handle.set_display_show_time(true);
+        let records = handle.take_update().records;
+        assert_eq!(records.iter().map(|v| (v.seq, v.dir)).collect::<Vec<_>>(), vec![(0, Direction::Rx), (1, Direction::Tx)]);
+        // 设备数据原样保留，渲染结果不含标记
+        let json = serde_json::to_value(&records[0]).unwrap();
+        assert_eq!(json["text"], "<img src=x>");
+        assert_eq!(json["data"].as_array().unwrap().len(), 11);
+        assert!(json["ts"].as_i64().unwrap() > 0);
+        assert!(!handle.recv_buffer_to_string().contains("<strong>"));
+    }
+
+    #[test]
+    fn test_time_format() {
+        let mut handle = MsgHandle::new();
+        handle.add_buffer(vec![0x30]);
+        thread::sleep(Duration::from_millis(20));
+        handle.add_buffer(vec![0x31]);
+        let records = handle.take_update().records;
+        assert!(records[1].mono >= records[0].mono + 20_000_000);
+        assert_eq!(records[1].delta, records[1].mono - records[0].mono);
+
+        handle.set_display_show_time(true);
+        handle.set_time_format(TimeFormat::Date);
+        let date = Local::now().format("%Y-%m-%d").to_string();
+        assert!(handle.recv_buffer_to_string().starts_with(&format!("[{date} ")));
+        handle.set_time_format(TimeFormat::Delta);
+        let row = &handle.query(1, 1, None).rows[0];
+        assert!(row.starts_with("[+0.0") && row.ends_with("]: 1\r\n"));
+        assert_eq!(TimeFormat::Relative.format(&RecvRecord { mono: 1_234_567_890, ..records[0].clone() }), "+1.234567");
+    }
+
+    #[test]
+    fn test_line_mode() {
+        assert_eq!(LineEnding::Any.find(b"ab\r"), None);
+        assert_eq!(LineEnding::Any.find(b"ab\r\ncd"), Some(4));
+        assert_eq!(LineEnding::Any.find(b"ab\rcd"), Some(3));
+        assert_eq!(LineEnding::CrLf.find(b"a\nb\r\n"), Some(5));
+
+        let mut handle = MsgHandle::new();
+        handle.set_line_option(Some(LineOption { ending: LineEnding::Lf, timeout: 30, ..Default::default() }));
+        handle.add_buffer(b"te".to_vec());
+        thread::sleep(Duration::from_millis(5));
+        handle.add_buffer(b"mp=1\nte".to_vec());
+        handle.add_buffer(b"mp=2\n\nhalf".to_vec());
+        let records = handle.take_update().records;
+        assert_eq!(records.iter().map(|v| v.text.as_str()).collect::<Vec<_>>(), vec!["temp=1\n", "temp=2\n", "\n"]);
+        // 第一行的时间为第一个字节的到达时间
+        assert!(records[1].mono >= records[0].mono + 5_000_000);
+        assert_eq!(handle.recv_count, 19);
+
+        assert_eq!(handle.flush_line(false), 0);
+        thread::sleep(Duration::from_millis(40));
+        assert_eq!(handle.flush_line(false), 4);
+        handle.add_buffer(b"x".to_vec());
+        handle.set_line_option(None);
+        assert_eq!(update(&mut handle), (false, "halfx".to_string()));
+
+        // 一直等待换行符时，超长的行按长度截断
+        handle.set_line_option(Some(LineOption { ending: LineEnding::Lf, timeout: 0, max_len: 4 }));
+        handle.add_buffer(b"abcdefghij".to_vec());
+        handle.add_buffer(b"k\nlmnopq\n".to_vec());
+        let records = handle.take_update().records;
+        assert_eq!(records.iter().map(|v| v.text.as_str()).collect::<Vec<_>>(), vec!["abcd", "efgh", "ijk\n", "lmno", "pq\n"]);
+        assert_eq!(handle.flush_line(true), 0);
+    }
+
+    #[test]
+    fn test_split_char() {
+        let mut handle = MsgHandle::new();
+        handle.add_buffer(BUFFER_UTF8[..4].to_vec());
+        handle.add_buffer(BUFFER_UTF8[4..].to_vec());
+        handle.add_buffer(vec![0xFF]);
+        assert_eq!(handle.recv_buffer_to_string(), "啊啊\\xFF");
+        handle.set_display_code(MsgCode(encoding_rs::GBK));
+        assert_eq!(handle.recv_buffer_to_string(), "鍟婂晩\\xFF");
+    }
+
+    #[test]
+    fn test_display_newline() {
+        let mut handle = MsgHandle::new();
+        handle.add_buffer(b"a\r".to_vec());
+        handle.add_buffer(b"\nb\rc".to_vec());
+        handle.add_send(b"AT\r");
+        handle.set_newline_option(NewlineOption { display: Some(Newline::Lf), ..Default::default() });
+        assert_eq!(snapshot(&mut handle), "a\nb\nc");
+        assert_eq!(handle.recv_buffer[2].text, "AT\n");
+        handle.add_buffer(b"\r\n".to_vec());
+        assert_eq!(update(&mut handle), (false, "\n".to_string()));
+        // 原始数据与计数不变
+        assert_eq!(handle.entries().map(|v| v.2.to_vec()).collect::<Vec<_>>(), vec![b"a\r".to_vec(), b"\nb\rc".to_vec(), b"AT\r".to_vec(), b"\r\n".to_vec()]);
+        assert_eq!((handle.recv_count, handle.send_count), (8, 3));
+
+        handle.set_newline_option(NewlineOption::default());
+        assert_eq!(snapshot(&mut handle), "a\r\nb\rc\r\n");
+    }
+
+    #[test]
+    fn test_clear_split() {
+        let mut handle = MsgHandle::new();
+        handle.set_newline_option(NewlineOption { display: Some(Newline::Lf), ..Default::default() });
+        handle.add_buffer(vec![b'a', 0xE5, 0x95]);
+        handle.add_buffer(b"b\r".to_vec());
+        handle.clear_buffer();
+        // 清空前的半个字符与 \r 不影响之后的数据
+        handle.add_buffer(b"\nc".to_vec());
+        assert_eq!(snapshot(&mut handle), "\nc");
+        handle.clear_buffer();
+        handle.add_buffer(vec![0xE5, 0x95]);
+        handle.clear_buffer();
+        handle.add_buffer(b"x".to_vec());
+        assert_eq!(snapshot(&mut handle), "x");
+    }
+
     #[test]
     fn test_handles() {
         let mut handles = MsgHandles::new();
@@ -206,7 +1366,7 @@ mod test {
             if let Some(x) = hand_clone.lock().unwrap().get_mut("test") {
                 x.add_buffer(BUFFER_UTF8.to_vec());
                 x.recv_count = 98999;
-                x.set_display_code(MsgCode::GBK);
+                x.set_display_code(MsgCode(encoding_rs::GBK));
             }
         });
 
@@ -0,0 +1,391 @@
+use encoding_rs::{EncoderResult, Encoding, UTF_16BE, UTF_16LE};
+use serde::Serialize;
+use crate::codec::Newline;
+
+
+// 解析错误，start/end 为 UTF-16 下标，与前端字符串下标一致
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct ParseError {
+    pub start: usize,
+    pub end: usize,
+    pub msg: String,
+}
+
+impl ParseError {
+    fn new(start: usize, end: usize, msg: impl Into<String>) -> Self {
+        Self { start, end, msg: msg.into() }
+    }
+}
+
+// 转义解析结果，文本部分后续按发送编码转换，字节部分原样发送
+#[derive(Clone, Debug, PartialEq)]
+pub enum Segment {
+    // 文本及每个字符在输入中的 UTF-16 范围，用于定位编码错误
+    Text(String, Vec<(usize, usize)>),
+    Bytes(Vec<u8>),
+}
+
+impl Segment {
+    // 不做转义的整段文本
+    pub fn text(input: &str) -> Self {
+        let mut ranges = Vec::with_capacity(input.len());
+        let mut pos = 0;
+        for c in input.chars() {
+            ranges.push((pos, pos + c.len_utf16()));
+            pos += c.len_utf16();
+        }
+        Segment::Text(input.to_string(), ranges)
+    }
+}
+
+fn is_hex_separator(c: char) -> bool {
+    c.is_whitespace() || matches!(c, ',' | ';' | ':' | '-')
+}
+
+// 解析十六进制输入，支持 "AA BB"、"0xAA,0xBB"、"AABB" 等格式
+// 单个字符视为一个字节，例如 "1 2" => [0x01, 0x02]
+pub fn parse_hex(input: &str) -> Result<Vec<u8>, Vec<ParseError>> {
+    let mut bytes = Vec::new();
+    let mut errors = Vec::new();
+
+    // 按分隔符切分，同时记录每段的 UTF-16 起止位置
+    let mut tokens: Vec<(usize, usize, String)> = Vec::new();
+    let mut pos = 0;
+    let mut token = String::new();
+    let mut start = 0;
+    for c in input.chars() {
+        if is_hex_separator(c) {
+            if !token.is_empty() {
+                tokens.push((start, pos, std::mem::take(&mut token)));
+            }
+        } else {
+            if token.is_empty() {
+                start = pos;
+            }
+            token.push(c);
+        }
+        pos += c.len_utf16();
+    }
+    if !token.is_empty() {
+        tokens.push((start, pos, token));
+    }
+
+    for (start, end, token) in tokens {
+        let digits = token.strip_prefix("0x").or_else(|| token.strip_prefix("0X")).unwrap_or(&token);
+        if digits.is_empty() {
+            errors.push(ParseError::new(start, end, "缺少十六进制数字"));
+            continue;
+        }
+        if let Some((i, c)) = digits.char_indices().find(|(_, c)| !c.is_ascii_hexdigit()) {
+            // 定位到非法字符本身
+            let offset = start + (token.len() - digits.len()) + digits[..i].encode_utf16().count();
+            errors.push(ParseError::new(offset, offset + c.len_utf16(), format!("非法十六进制字符 '{c}'")));
+            continue;
+        }
+        if digits.len() == 1 {
+            bytes.push(u8::from_str_radix(digits, 16).unwrap());
+        } else if digits.len() % 2 == 1 {
+            errors.push(ParseError::new(start, end, "十六进制字符个数为奇数"));
+        } else {
+            for i in (0..digits.len()).step_by(2) {
+                bytes.push(u8::from_str_radix(&digits[i..i + 2], 16).unwrap());
+            }
+        }
+    }
+
+    if errors.is_empty() { Ok(bytes) } else { Err(errors) }
+}
+
+// 解析 C 风格转义：\r \n \t \0 \\ \" \' \a \b \f \v \e \xHH \NNN(八进制) \u{H..}
+// \xHH 与八进制转义产生原始字节，其余均为文本
+pub fn parse_escape(input: &str) -> Result<Vec<Segment>, Vec<ParseError>> {
+    let mut segments = Vec::new();
+    let mut errors = Vec::new();
+    let mut text = String::new();
+    let mut ranges = Vec::new();
+    let mut bytes = Vec::new();
+
+    let chars = input.chars().collect::<Vec<char>>();
+    // 每个字符的 UTF-16 起始位置
+    let mut offsets = Vec::with_capacity(chars.len() + 1);
+    let mut pos = 0;
+    for c in &chars {
+        offsets.push(pos);
+        pos += c.len_utf16();
+    }
+    offsets.push(pos);
+
+    let flush_text = |text: &mut String, ranges: &mut Vec<(usize, usize)>, segments: &mut Vec<Segment>| {
+        if !text.is_empty() {
+            segments.push(Segment::Text(std::mem::take(text), std::mem::take(ranges)));
+        }
+    };
+    let flush_bytes = |bytes: &mut Vec<u8>, segments: &mut Vec<Segment>| {
+        if !bytes.is_empty() {
+            segments.push(Segment::Bytes(std::mem::take(bytes)));
+        }
+    };
+
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c != '\\' {
+            flush_bytes(&mut bytes, &mut segments);
+            text.push(c);
+            ranges.push((offsets[i], offsets[i + 1]));
+            i += 1;
+            continue;
+        }
+        let start = offsets[i];
+        let Some(&e) = chars.get(i + 1) else {
+            errors.push(ParseError::new(start, offsets[i + 1], "转义序列不完整"));
+            break;
+        };
+        let simple = match e {
+            'r' => Some('\r'),
+            'n' => Some('\n'),
+            't' => Some('\t'),
+            '\\' => Some('\\'),
+            '"' => Some('"'),
+            '\'' => Some('\''),
+            'a' => Some('\x07'),
+            'b' => Some('\x08'),
+            'f' => Some('\x0C'),
+            'v' => Some('\x0B'),
+            'e' => Some('\x1B'),
+            _ => None,
+        };
+        if let Some(v) = simple {
+            flush_bytes(&mut bytes, &mut segments);
+            text.push(v);
+            ranges.push((start, offsets[i + 2]));
+            i += 2;
+            continue;
+        }
+        match e {
+            'x' => {
+                let digits = chars[i + 2..].iter().take(2).take_while(|v| v.is_ascii_hexdigit()).collect::<String>();
+                if digits.is_empty() {
+                    errors.push(ParseError::new(start, offsets[i + 2], "\\x 后缺少十六进制数字"));
+                    i += 2;
+                } else {
+                    flush_text(&mut text, &mut ranges, &mut segments);
+                    bytes.push(u8::from_str_radix(&digits, 16).unwrap());
+                    i += 2 + digits.len();
+                }
+            }
+            '0'..='7' => {
+                let digits = chars[i + 1..].iter().take(3).take_while(|v| ('0'..='7').contains(*v)).collect::<String>();
+                let end = offsets[i + 1 + digits.len()];
+                match u8::from_str_radix(&digits, 8) {
+                    Ok(v) => {
+                        flush_text(&mut text, &mut ranges, &mut segments);
+                        bytes.push(v);
+                    }
+                    Err(_) => errors.push(ParseError::new(start, end, "八进制转义超出字节范围")),
+                }
+                i += 1 + digits.len();
+            }
+            'u' => {
+                // \u{1F600} 或 \u4E2D
+                let (digits, len) = if chars.get(i + 2) == Some(&'{') {
+                    match chars[i + 3..].iter().position(|v| *v == '}') {
+                        Some(p) => (chars[i + 3..i + 3 + p].iter().collect::<String>(), p + 2),
+                        None => (String::new(), 1),
+                    }
+                } else {
+                    let digits = chars[i + 2..].iter().take(4).collect::<String>();
+                    let len = digits.chars().count();
+                    (digits, len)
+                };
+                let end = offsets[(i + 2 + len).min(chars.len())];
+                match u32::from_str_radix(&digits, 16).ok().and_then(char::from_u32) {
+                    Some(v) => {
+                        flush_bytes(&mut bytes, &mut segments);
+                        text.push(v);
+                        ranges.push((start, end));
+                    }
+                    None => errors.push(ParseError::new(start, end, "无效的 Unicode 转义")),
+                }
+                i += 2 + len;
+            }
+            _ => {
+                errors.push(ParseError::new(start, offsets[i + 2], format!("未知转义序列 '\\{e}'")));
+                i += 2;
+            }
+        }
+    }
+    flush_text(&mut text, &mut ranges, &mut segments);
+    flush_bytes(&mut bytes, &mut segments);
+
+    if errors.is_empty() { Ok(segments) } else { Err(errors) }
+}
+
+// 文本部分的 \n、\r\n 与单独的 \r 统一替换为指定换行符，字节部分不受影响
+// 替换后的字符沿用原换行符在输入中的范围
+pub fn translate_newlines(segments: Vec<Segment>, newline: Newline) -> Vec<Segment> {
+    segments.into_iter().map(|s| {
+        let Segment::Text(text, ranges) = s else {
+            return s;
+        };
+        let mut out = String::with_capacity(text.len());
+        let mut out_ranges = Vec::with_capacity(ranges.len());
+        let mut chars = text.chars().zip(ranges).peekable();
+        while let Some((c, range)) = chars.next() {
+            let range = match c {
+                '\r' => match chars.peek() {
+                    Some(('\n', next)) => {
+                        let range = (range.0, next.1);
+                        chars.next();
+                        range
+                    }
+                    _ => range,
+                },
+                '\n' => range,
+                _ => {
+                    out.push(c);
+                    out_ranges.push(range);
+                    continue;
+                }
+            };
+            out.push_str(newline.as_str());
+            out_ranges.extend(newline.as_str().chars().map(|_| range));
+        }
+        Segment::Text(out, out_ranges)
+    }).collect()
+}
+
+// 在末尾追加换行符
+pub fn append_newline(segments: &mut Vec<Segment>, newline: Newline) {
+    let end = segments.iter().rev().find_map(|s| match s {
+        Segment::Text(_, ranges) => ranges.last().map(|v| v.1),
+        Segment::Bytes(_) => None,
+    }).unwrap_or(0);
+    let text = newline.as_str();
+    segments.push(Segment::Text(text.to_string(), text.chars().map(|_| (end, end)).collect()));
+}
+
+// 按发送编码转换文本，字节部分原样保留
+// 无法用该编码表示的字符不做替换，逐个以错误形式返回
+pub fn encode_segments(segments: &[Segment], encoding: &'static Encoding) -> Result<Vec<u8>, Vec<ParseError>> {
+    let mut v = Vec::new();
+    let mut errors = Vec::new();
+    for s in segments {
+        match s {
+            Segment::Text(t, ranges) => encode_text(t, ranges, encoding, &mut v, &mut errors),
+            Segment::Bytes(b) => v.extend_from_slice(b),
+        }
+    }
+    if errors.is_empty() { Ok(v) } else { Err(errors) }
+}
+
+fn encode_text(text: &str, ranges: &[(usize, usize)], encoding: &'static Encoding, out: &mut Vec<u8>, errors: &mut Vec<ParseError>) {
+    // encoding_rs 不提供 UTF-16 编码器
+    if encoding == UTF_16LE || encoding == UTF_16BE {
+        for u in text.encode_utf16() {
+            out.extend_from_slice(&if encoding == UTF_16LE { u.to_le_bytes() } else { u.to_be_bytes() });
+        }
+        return;
+    }
+
+    let mut encoder = encoding.new_encoder();
+    let mut buf = vec![0u8; encoder.max_buffer_length_from_utf8_without_replacement(text.len()).unwrap_or(text.len() * 4 + 16)];
+    let mut read = 0;
+    loop {
+        let (result, r, w) = encoder.encode_from_utf8_without_replacement(&text[read..], &mut buf, true);
+        read += r;
+        out.extend_from_slice(&buf[..w]);
+        match result {
+            EncoderResult::InputEmpty => break,
+            EncoderResult::OutputFull => {
+                let len = buf.len() * 2;
+                buf.resize(len, 0);
+            }
+            EncoderResult::Unmappable(c) => {
+                // 出错字符刚被读取，换算为字符序号定位输入位置
+                let index = text[..read - c.len_utf8()].chars().count();
+                let (start, end) = ranges.get(index).copied().unwrap_or_default();
+                errors.push(ParseError::new(start, end, format!("字符 '{c}' 无法用 {} 编码", encoding.name())));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::codec::encoding_for_label;
+
+    #[test]
+    fn test_parse_hex() {
+        assert_eq!(parse_hex("AA BB"), Ok(vec![0xAA, 0xBB]));
+        assert_eq!(parse_hex("0xAA,0xBB"), Ok(vec![0xAA, 0xBB]));
+        assert_eq!(parse_hex("AABB"), Ok(vec![0xAA, 0xBB]));
+        assert_eq!(parse_hex(" 1 02 aa b 1c\n"), Ok(vec![0x01, 0x02, 0xAA, 0x0B, 0x1C]));
+        assert_eq!(parse_hex(""), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_parse_hex_error() {
+        assert_eq!(parse_hex("AA ZZ BBB"), Err(vec![
+            ParseError::new(3, 4, "非法十六进制字符 'Z'"),
+            ParseError::new(6, 9, "十六进制字符个数为奇数"),
+        ]));
+        assert_eq!(parse_hex("中 0xAG").unwrap_err()[1], ParseError::new(5, 6, "非法十六进制字符 'G'"));
+        assert_eq!(parse_hex("0x").unwrap_err()[0], ParseError::new(0, 2, "缺少十六进制数字"));
+    }
+
+    #[test]
+    fn test_parse_escape() {
+        let v = parse_escape(r"AT\r\n\x02中\101\u{1F600}").unwrap();
+        assert_eq!(v, vec![
+            Segment::Text("AT\r\n".to_string(), vec![(0, 1), (1, 2), (2, 4), (4, 6)]),
+            Segment::Bytes(vec![0x02]),
+            Segment::Text("中".to_string(), vec![(10, 11)]),
+            Segment::Bytes(vec![0x41]),
+            Segment::Text("😀".to_string(), vec![(15, 24)]),
+        ]);
+        assert_eq!(encode_segments(&parse_escape(r"\\\0").unwrap(), encoding_rs::UTF_8), Ok(vec![b'\\', 0]));
+    }
+
+    #[test]
+    fn test_parse_escape_error() {
+        assert_eq!(parse_escape(r"中\q\x"), Err(vec![
+            ParseError::new(1, 3, "未知转义序列 '\\q'"),
+            ParseError::new(3, 5, "\\x 后缺少十六进制数字"),
+        ]));
+        assert_eq!(parse_escape(r"\777").unwrap_err()[0], ParseError::new(0, 4, "八进制转义超出字节范围"));
+        assert_eq!(parse_escape("a\\").unwrap_err()[0], ParseError::new(1, 2, "转义序列不完整"));
+    }
+
+    #[test]
+    fn test_encode_segments() {
+        let gbk = encoding_for_label("gbk").unwrap();
+        let v = parse_escape(r"啊\x00A").unwrap();
+        assert_eq!(encode_segments(&v, gbk), Ok(vec![0xB0, 0xA1, 0x00, 0x41]));
+        assert_eq!(encode_segments(&[Segment::text("A中")], encoding_for_label("utf-16be").unwrap()), Ok(vec![0x00, 0x41, 0x4E, 0x2D]));
+        // 不可表示的字符按输入位置报告，不做替换
+        assert_eq!(encode_segments(&parse_escape(r"a😀b\u00e9").unwrap(), encoding_for_label("shift_jis").unwrap()), Err(vec![
+            ParseError::new(1, 3, "字符 '😀' 无法用 Shift_JIS 编码"),
+            ParseError::new(4, 10, "字符 'é' 无法用 Shift_JIS 编码"),
+        ]));
+    }
+
+    #[test]
+    fn test_translate_newlines() {
+        let v = translate_newlines(parse_escape(r"a\r\nb\r\x0D\n").unwrap(), Newline::CrLf);
+        assert_eq!(v, vec![
+            Segment::Text("a\r\nb\r\n".to_string(), vec![(0, 1), (1, 5), (1, 5), (5, 6), (6, 8), (6, 8)]),
+            // 转义为字节的换行符原样发送
+            Segment::Bytes(vec![0x0D]),
+            Segment::Text("\r\n".to_string(), vec![(12, 14), (12, 14)]),
+        ]);
+        let v = translate_newlines(vec![Segment::text("a\nb\r")], Newline::Cr);
+        assert_eq!(encode_segments(&v, encoding_rs::UTF_8), Ok(b"a\rb\r".to_vec()));
+
+        let mut v = vec![Segment::text("AT")];
+        append_newline(&mut v, Newline::CrLf);
+        assert_eq!(v[1], Segment::Text("\r\n".to_string(), vec![(2, 2), (2, 2)]));
+        assert_eq!(encode_segments(&v, encoding_for_label("utf-16le").unwrap()), Ok(vec![0x41, 0, 0x54, 0, 0x0D, 0, 0x0A, 0]));
+    }
+}
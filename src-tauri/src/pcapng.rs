@@ -0,0 +1,149 @@
+use std::io::Write;
+use anyhow::Result;
+use crate::logger::Direction;
+
+
+// 自定义链路类型 LINKTYPE_USER0，可选 147~162，供 Wireshark 自定义解析器使用
+pub const LINKTYPE_USER0: u16 = 147;
+
+const SHB_TYPE: u32 = 0x0A0D_0D0A;
+const IDB_TYPE: u32 = 0x0000_0001;
+const EPB_TYPE: u32 = 0x0000_0006;
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+
+const OPT_END: u16 = 0;
+const IF_NAME: u16 = 2;
+const IF_TSRESOL: u16 = 9;
+const EPB_FLAGS: u16 = 2;
+
+// pcapng 写入器，单接口，每条收发数据一个 EPB
+// 时间戳精度为纳秒，方向写入 epb_flags 的入站/出站位
+pub struct PcapngWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> PcapngWriter<W> {
+    pub fn new(mut writer: W, linktype: u16, if_name: &str) -> Result<Self> {
+        // Section Header Block，节长度未知填 -1
+        let mut body = Vec::new();
+        body.extend_from_slice(&BYTE_ORDER_MAGIC.to_le_bytes());
+        body.extend_from_slice(&1u16.to_le_bytes());
+        body.extend_from_slice(&0u16.to_le_bytes());
+        body.extend_from_slice(&(-1i64).to_le_bytes());
+        push_option(&mut body, OPT_END, &[]);
+        write_block(&mut writer, SHB_TYPE, &body)?;
+
+        // Interface Description Block，snaplen 0 表示不截断
+        let mut body = Vec::new();
+        body.extend_from_slice(&linktype.to_le_bytes());
+        body.extend_from_slice(&0u16.to_le_bytes());
+        body.extend_from_slice(&0u32.to_le_bytes());
+        if !if_name.is_empty() {
+            push_option(&mut body, IF_NAME, if_name.as_bytes());
+        }
+        push_option(&mut body, IF_TSRESOL, &[9]);
+        push_option(&mut body, OPT_END, &[]);
+        write_block(&mut writer, IDB_TYPE, &body)?;
+        writer.flush()?;
+
+        Ok(Self { writer })
+    }
+
+    // ts 为 Unix 纳秒时间戳，写入后不刷新，需要落盘时调用 flush
+    pub fn write_packet(&mut self, ts: u64, dir: Direction, data: &[u8]) -> Result<()> {
+        let mut body = Vec::with_capacity(data.len() + 40);
+        body.extend_from_slice(&0u32.to_le_bytes());
+        body.extend_from_slice(&((ts >> 32) as u32).to_le_bytes());
+        body.extend_from_slice(&(ts as u32).to_le_bytes());
+        body.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        body.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        body.extend_from_slice(data);
+        pad(&mut body);
+        push_option(&mut body, EPB_FLAGS, &direction_flag(dir).to_le_bytes());
+        push_option(&mut body, OPT_END, &[]);
+        write_block(&mut self.writer, EPB_TYPE, &body)?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+// epb_flags 低两位：01 入站，10 出站
+fn direction_flag(dir: Direction) -> u32 {
+    match dir {
+        Direction::Rx => 0b01,
+        Direction::Tx => 0b10,
+    }
+}
+
+// 按 4 字节对齐
+fn pad(buf: &mut Vec<u8>) {
+    let len = buf.len().div_ceil(4) * 4;
+    buf.resize(len, 0);
+}
+
+fn push_option(buf: &mut Vec<u8>, code: u16, value: &[u8]) {
+    buf.extend_from_slice(&code.to_le_bytes());
+    buf.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    buf.extend_from_slice(value);
+    pad(buf);
+}
+
+// 块结构：类型、总长度、内容、总长度
+fn write_block(writer: &mut impl Write, block_type: u32, body: &[u8]) -> Result<()> {
+    let len = (body.len() + 12) as u32;
+    writer.write_all(&block_type.to_le_bytes())?;
+    writer.write_all(&len.to_le_bytes())?;
+    writer.write_all(body)?;
+    writer.write_all(&len.to_le_bytes())?;
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn u32_at(buf: &[u8], i: usize) -> u32 {
+        u32::from_le_bytes(buf[i..i + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_pcapng_blocks() {
+        let mut buf = Vec::new();
+        {
+            let mut w = PcapngWriter::new(&mut buf, LINKTYPE_USER0, "COM1").unwrap();
+            w.write_packet(1_700_000_000_123_456_789, Direction::Tx, b"hello").unwrap();
+        }
+
+        // SHB
+        assert_eq!(u32_at(&buf, 0), SHB_TYPE);
+        let shb_len = u32_at(&buf, 4) as usize;
+        assert_eq!(u32_at(&buf, shb_len - 4) as usize, shb_len);
+        assert_eq!(u32_at(&buf, 8), BYTE_ORDER_MAGIC);
+
+        // IDB：链路类型、if_name、if_tsresol
+        let idb = &buf[shb_len..];
+        let idb_len = u32_at(idb, 4) as usize;
+        assert_eq!(u32_at(idb, 0), IDB_TYPE);
+        assert_eq!(u16::from_le_bytes([idb[8], idb[9]]), LINKTYPE_USER0);
+        assert_eq!(&idb[20..24], b"COM1");
+        assert_eq!(&idb[24..29], &[9, 0, 1, 0, 9]);
+
+        // EPB：时间戳、长度、数据、方向
+        let epb = &idb[idb_len..];
+        let epb_len = u32_at(epb, 4) as usize;
+        assert_eq!(epb.len(), epb_len);
+        assert_eq!(u32_at(epb, 0), EPB_TYPE);
+        let ts = ((u32_at(epb, 12) as u64) << 32) | u32_at(epb, 16) as u64;
+        assert_eq!(ts, 1_700_000_000_123_456_789);
+        assert_eq!(u32_at(epb, 20), 5);
+        assert_eq!(&epb[28..33], b"hello");
+        assert_eq!(&epb[36..40], &[2, 0, 4, 0]);
+        assert_eq!(u32_at(epb, 40), 0b10);
+        assert_eq!(epb_len % 4, 0);
+    }
+}
@@ -0,0 +1,260 @@
+use std::collections::VecDeque;
+use std::io::Write;
+use anyhow::{format_err, Result};
+use chrono::{DateTime, Local};
+use encoding_rs::Encoding;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use crate::codec::StreamDecoder;
+use crate::logger::csv_field;
+
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PlotKind {
+    // 逗号、分号、制表符或空格分隔的数值，可写作 name:value
+    #[default]
+    Csv,
+    // name=value 或 name:value
+    KeyValue,
+    // 正则捕获组，命名捕获组使用组名，否则使用组序号
+    Regex,
+}
+
+// 绘图配置
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct PlotOption {
+    pub kind: PlotKind,
+    // kind 为 regex 时的正则表达式
+    pub pattern: String,
+    // 保留的数据点上限，超出后丢弃最早的数据点
+    pub max_points: usize,
+}
+
+impl Default for PlotOption {
+    fn default() -> Self {
+        Self {
+            kind: PlotKind::default(),
+            pattern: String::new(),
+            max_points: 10000,
+        }
+    }
+}
+
+// 一行解析出的全部数值，通过 plot_{id} 事件批量发往前端
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct PlotPoint {
+    // 微秒时间戳
+    pub ts: i64,
+    pub values: Vec<(String, f64)>,
+}
+
+// 分隔符两侧允许空白
+const SEPARATORS: [char; 4] = [',', ';', '\t', ' '];
+
+// 未结束的行长度上限，超出后丢弃该行，到下一个换行符后重新开始解析
+const LINE_LIMIT: usize = 4096;
+
+#[derive(Debug)]
+pub struct Plotter {
+    option: PlotOption,
+    regex: Option<Regex>,
+    decoder: StreamDecoder,
+    // 尚未结束的行
+    line: String,
+    // 当前行已超出长度上限被丢弃
+    overflow: bool,
+    // 停止后不再解析新数据，历史保留到重新开始或断开连接
+    running: bool,
+    // 按首次出现的顺序记录序列名，作为导出的列
+    names: Vec<String>,
+    history: VecDeque<PlotPoint>,
+    // 尚未发往前端的数据点
+    pending: Vec<PlotPoint>,
+}
+
+impl Plotter {
+    pub fn new(option: PlotOption, code: &'static Encoding) -> Result<Self> {
+        let regex = match option.kind {
+            PlotKind::Regex => Some(Regex::new(&option.pattern).map_err(|e| format_err!("正则表达式错误：{e}"))?),
+            _ => None,
+        };
+        Ok(Self {
+            option,
+            regex,
+            decoder: StreamDecoder::new(code),
+            line: String::new(),
+            overflow: false,
+            running: true,
+            names: Vec::new(),
+            history: VecDeque::new(),
+            pending: Vec::new(),
+        })
+    }
+
+    // 输入接收数据，按 \n 分行解析，不含数值的行被忽略
+    pub fn feed(&mut self, data: &[u8], time: DateTime<Local>) {
+        if !self.running {
+            return;
+        }
+        self.line.push_str(&self.decoder.decode(data));
+        while let Some(i) = self.line.find('\n') {
+            let line = self.line.drain(..=i).collect::<String>();
+            if std::mem::take(&mut self.overflow) {
+                continue;
+            }
+            let values = self.parse(line.trim_end_matches(['\r', '\n']));
+            if values.is_empty() {
+                continue;
+            }
+            for (name, _) in &values {
+                if !self.names.contains(name) {
+                    self.names.push(name.clone());
+                }
+            }
+            let point = PlotPoint { ts: time.timestamp_micros(), values };
+            if self.history.len() >= self.option.max_points.max(1) {
+                self.history.pop_front();
+            }
+            self.history.push_back(point.clone());
+            self.pending.push(point);
+        }
+        if self.line.len() > LINE_LIMIT {
+            self.line.clear();
+            self.overflow = true;
+        }
+    }
+
+    // 停止解析，已有数据仍可查询与导出
+    pub fn stop(&mut self) -> bool {
+        let running = std::mem::replace(&mut self.running, false);
+        self.line.clear();
+        self.overflow = false;
+        running
+    }
+
+    fn parse(&self, line: &str) -> Vec<(String, f64)> {
+        match self.option.kind {
+            PlotKind::Csv => line.split(SEPARATORS).filter(|v| !v.is_empty()).enumerate().filter_map(|(i, v)| {
+                match v.split_once(':') {
+                    Some((name, value)) => Some((name.trim().to_string(), value.trim().parse().ok()?)),
+                    None => Some((i.to_string(), v.parse().ok()?)),
+                }
+            }).collect(),
+            PlotKind::KeyValue => line.split(SEPARATORS).filter_map(|v| {
+                let (name, value) = v.split_once(['=', ':'])?;
+                Some((name.trim().to_string(), value.trim().parse().ok()?))
+            }).collect(),
+            PlotKind::Regex => {
+                let Some(regex) = &self.regex else {
+                    return Vec::new();
+                };
+                let Some(caps) = regex.captures(line) else {
+                    return Vec::new();
+                };
+                regex.capture_names().enumerate().skip(1).filter_map(|(i, name)| {
+                    let value = caps.get(i)?.as_str().trim().parse().ok()?;
+                    Some((name.map(|v| v.to_string()).unwrap_or_else(|| i.to_string()), value))
+                }).collect()
+            }
+        }
+    }
+
+    // 取出尚未发往前端的数据点
+    pub fn take_batch(&mut self) -> Vec<PlotPoint> {
+        std::mem::take(&mut self.pending)
+    }
+
+    pub fn history(&self) -> Vec<PlotPoint> {
+        self.history.iter().cloned().collect()
+    }
+
+    // 序列名，按首次出现的顺序
+    pub fn names(&self) -> Vec<String> {
+        self.names.clone()
+    }
+}
+
+// 导出为 CSV，每个序列一列，某行缺少的序列留空，返回导出的行数
+pub fn write_csv(names: &[String], points: &[PlotPoint], w: &mut impl Write) -> Result<usize> {
+    let header = names.iter().map(|v| csv_field(v)).collect::<Vec<String>>().join(",");
+    writeln!(w, "time,{header}")?;
+    for point in points {
+        let time = DateTime::from_timestamp_micros(point.ts).unwrap_or_default().with_timezone(&Local);
+        let row = names.iter().map(|name| {
+            point.values.iter().find(|v| &v.0 == name).map(|v| v.1.to_string()).unwrap_or_default()
+        }).collect::<Vec<String>>().join(",");
+        writeln!(w, "{},{row}", time.format("%Y-%m-%d %H:%M:%S%.6f"))?;
+    }
+    w.flush()?;
+    Ok(points.len())
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn values(plotter: &mut Plotter) -> Vec<Vec<(String, f64)>> {
+        plotter.take_batch().into_iter().map(|v| v.values).collect()
+    }
+
+    fn pair(name: &str, value: f64) -> (String, f64) {
+        (name.to_string(), value)
+    }
+
+    #[test]
+    fn test_plot_parse() {
+        let now = Local::now();
+        let mut plotter = Plotter::new(PlotOption::default(), encoding_rs::UTF_8).unwrap();
+        plotter.feed(b"1.5, 2\r\nte", now);
+        plotter.feed(b"mp:3 x -4\nhello\n", now);
+        assert_eq!(values(&mut plotter), vec![
+            vec![pair("0", 1.5), pair("1", 2.0)],
+            vec![pair("temp", 3.0), pair("2", -4.0)],
+        ]);
+
+        let option = PlotOption { kind: PlotKind::KeyValue, ..Default::default() };
+        let mut plotter = Plotter::new(option, encoding_rs::UTF_8).unwrap();
+        plotter.feed(b"t=12.3,h=45.1\n", now);
+        assert_eq!(values(&mut plotter), vec![vec![pair("t", 12.3), pair("h", 45.1)]]);
+
+        let option = PlotOption { kind: PlotKind::Regex, pattern: r"T(?<temp>[\d.]+) (\d+)".to_string(), ..Default::default() };
+        let mut plotter = Plotter::new(option, encoding_rs::UTF_8).unwrap();
+        plotter.feed(b"T21.5 80\n", now);
+        assert_eq!(values(&mut plotter), vec![vec![pair("temp", 21.5), pair("2", 80.0)]]);
+        assert!(Plotter::new(PlotOption { kind: PlotKind::Regex, pattern: "(".to_string(), ..Default::default() }, encoding_rs::UTF_8).is_err());
+    }
+
+    #[test]
+    fn test_plot_line_limit() {
+        let now = Local::now();
+        let mut plotter = Plotter::new(PlotOption::default(), encoding_rs::UTF_8).unwrap();
+        for _ in 0..LINE_LIMIT {
+            plotter.feed(b"1 ", now);
+        }
+        assert!(plotter.line.len() <= LINE_LIMIT);
+        // 超长行的剩余部分被丢弃，之后的行正常解析
+        plotter.feed(b"2\n3\n", now);
+        assert_eq!(values(&mut plotter), vec![vec![pair("0", 3.0)]]);
+    }
+
+    #[test]
+    fn test_plot_export() {
+        let now = Local::now();
+        let option = PlotOption { kind: PlotKind::KeyValue, max_points: 2, ..Default::default() };
+        let mut plotter = Plotter::new(option, encoding_rs::UTF_8).unwrap();
+        plotter.feed(b"a=1\nb=2\na=3,b=4\n", now);
+        assert_eq!(plotter.history().len(), 2);
+        let mut out = Vec::new();
+        // 停止后不再解析，已有数据仍可导出
+        assert!(plotter.stop());
+        plotter.feed(b"a=5\n", now);
+        assert!(!plotter.stop());
+        assert_eq!(write_csv(&plotter.names(), &plotter.history(), &mut out).unwrap(), 2);
+        let out = String::from_utf8(out).unwrap();
+        let rows = out.lines().map(|v| v.split_once(',').map(|v| v.1).unwrap_or(v)).collect::<Vec<&str>>();
+        assert_eq!(rows, vec!["a,b", ",2", "3,4"]);
+    }
+}
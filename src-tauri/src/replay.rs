@@ -0,0 +1,384 @@
+use std::fs;
+use std::io::Read;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use anyhow::{format_err, Context, Result};
+use flate2::read::GzDecoder;
+use serde::Deserialize;
+use serde_json::Value;
+use crate::logger::{Direction, CSV_HEADER};
+use crate::parse::parse_hex;
+
+
+// 回放配置
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct ReplayOption {
+    // 回放哪一方向的数据
+    pub dir: Direction,
+    // 速度倍数，1 为原始间隔，0 为不等待尽快发送
+    pub speed: f64,
+}
+
+impl Default for ReplayOption {
+    fn default() -> Self {
+        Self {
+            dir: Direction::Tx,
+            speed: 1.0,
+        }
+    }
+}
+
+// 抓包中的一条记录，ts 为 Unix 纳秒时间戳
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReplayRecord {
+    pub ts: i64,
+    pub dir: Direction,
+    pub data: Vec<u8>,
+}
+
+// 读取本程序生成的 JSONL、CSV 或 pcapng 文件，支持 gzip 压缩的归档
+pub fn read_capture(path: &str) -> Result<Vec<ReplayRecord>> {
+    let mut buf = fs::read(path).with_context(|| format!("读取文件失败：{path}"))?;
+    if buf.starts_with(&[0x1F, 0x8B]) {
+        let mut v = Vec::new();
+        GzDecoder::new(buf.as_slice()).read_to_end(&mut v).context("解压失败")?;
+        buf = v;
+    }
+    if buf.starts_with(&0x0A0D_0D0Au32.to_le_bytes()) {
+        return read_pcapng(&buf);
+    }
+    let text = String::from_utf8(buf).context("文件不是 UTF-8 文本")?;
+    if text.starts_with(CSV_HEADER) {
+        read_csv(&text)
+    } else {
+        read_jsonl(&text)
+    }
+}
+
+fn parse_dir(s: &str) -> Result<Direction> {
+    match s {
+        "tx" => Ok(Direction::Tx),
+        "rx" => Ok(Direction::Rx),
+        _ => Err(format_err!("未知方向：{s}")),
+    }
+}
+
+fn parse_data(hex: &str) -> Result<Vec<u8>> {
+    parse_hex(hex).map_err(|e| format_err!("十六进制数据错误：{}", e[0].msg))
+}
+
+fn read_jsonl(text: &str) -> Result<Vec<ReplayRecord>> {
+    let mut records = Vec::new();
+    for (i, line) in text.lines().enumerate().filter(|(_, v)| !v.trim().is_empty()) {
+        let parse = || -> Result<ReplayRecord> {
+            let v: Value = serde_json::from_str(line)?;
+            Ok(ReplayRecord {
+                ts: v["ts"].as_i64().context("缺少 ts")?,
+                dir: parse_dir(v["dir"].as_str().unwrap_or_default())?,
+                data: parse_data(v["hex"].as_str().unwrap_or_default())?,
+            })
+        };
+        records.push(parse().with_context(|| format!("第 {} 行格式错误", i + 1))?);
+    }
+    Ok(records)
+}
+
+// 按 RFC 4180 拆分记录，引号内的逗号与换行属于字段内容
+fn csv_records(text: &str) -> Vec<Vec<String>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            (true, '"') => quoted = false,
+            (true, c) => field.push(c),
+            (false, '"') => quoted = true,
+            (false, ',') => record.push(std::mem::take(&mut field)),
+            (false, '\r') => {}
+            (false, '\n') => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            (false, c) => field.push(c),
+        }
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    records
+}
+
+fn read_csv(text: &str) -> Result<Vec<ReplayRecord>> {
+    let mut records = Vec::new();
+    // 跳过表头
+    for (i, v) in csv_records(text).iter().enumerate().skip(1) {
+        let parse = || -> Result<ReplayRecord> {
+            let field = |n: usize| v.get(n).map(|v| v.as_str()).context("字段数量不足");
+            Ok(ReplayRecord {
+                ts: field(1)?.parse()?,
+                dir: parse_dir(field(2)?)?,
+                data: parse_data(field(3)?)?,
+            })
+        };
+        records.push(parse().with_context(|| format!("第 {} 条记录格式错误", i))?);
+    }
+    Ok(records)
+}
+
+// 越界时返回 None，文件内容不可信
+fn u16_at(buf: &[u8], i: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(buf.get(i..i + 2)?.try_into().ok()?))
+}
+
+fn u32_at(buf: &[u8], i: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(buf.get(i..i + 4)?.try_into().ok()?))
+}
+
+// 遍历块内选项，返回 (代码, 值)，遇到越界的选项时停止
+fn options(buf: &[u8]) -> Vec<(u16, &[u8])> {
+    let mut v = Vec::new();
+    let mut i = 0;
+    while let (Some(code), Some(len)) = (u16_at(buf, i), u16_at(buf, i + 2)) {
+        let len = len as usize;
+        let Some(value) = buf.get(i + 4..i + 4 + len) else {
+            break;
+        };
+        if code == 0 {
+            break;
+        }
+        v.push((code, value));
+        i += 4 + len.div_ceil(4) * 4;
+    }
+    v
+}
+
+// 按接口时间精度换算为纳秒，if_tsresol 缺省为微秒
+fn ts_to_nanos(ts: u64, tsresol: Option<u8>) -> i64 {
+    match tsresol.unwrap_or(6) {
+        v if v & 0x80 != 0 => (ts as f64 * 1e9 / 2f64.powi((v & 0x7F) as i32)) as i64,
+        v if v <= 9 => ts.saturating_mul(10u64.pow(9 - v as u32)) as i64,
+        v => (ts / 10u64.pow((v as u32 - 9).min(19))) as i64,
+    }
+}
+
+// 解析小端 pcapng，读取各接口的时间精度与 EPB 中的数据和方向
+fn read_pcapng(buf: &[u8]) -> Result<Vec<ReplayRecord>> {
+    let bad = || format_err!("pcapng 块内容错误");
+    let mut records = Vec::new();
+    let mut resols = Vec::new();
+    let mut i = 0;
+    while i + 12 <= buf.len() {
+        let block_type = u32_at(buf, i).ok_or_else(bad)?;
+        let len = u32_at(buf, i + 4).ok_or_else(bad)? as usize;
+        if len < 12 || i + len > buf.len() {
+            return Err(format_err!("pcapng 块长度错误"));
+        }
+        let body = &buf[i + 8..i + len - 4];
+        match block_type {
+            0x0A0D_0D0A => {
+                if u32_at(body, 0).ok_or_else(bad)? != 0x1A2B_3C4D {
+                    return Err(format_err!("不支持大端 pcapng"));
+                }
+                resols.clear();
+            }
+            0x0000_0001 => {
+                let opts = body.get(8..).ok_or_else(bad)?;
+                resols.push(options(opts).iter().find(|v| v.0 == 9).and_then(|v| v.1.first().copied()));
+            }
+            0x0000_0006 => {
+                let field = |n: usize| u32_at(body, n).ok_or_else(bad);
+                let tsresol = *resols.get(field(0)? as usize).context("pcapng 接口不存在")?;
+                let ts = ((field(4)? as u64) << 32) | field(8)? as u64;
+                let cap_len = field(12)? as usize;
+                let data = body.get(20..20 + cap_len).context("pcapng 数据长度错误")?;
+                // 数据之后为可选的选项
+                let opts = body.get(20 + cap_len.div_ceil(4) * 4..).unwrap_or_default();
+                let flags = options(opts).iter()
+                    .find(|v| v.0 == 2).and_then(|v| u32_at(v.1, 0)).unwrap_or(0);
+                records.push(ReplayRecord {
+                    ts: ts_to_nanos(ts, tsresol),
+                    dir: if flags & 0b11 == 0b10 { Direction::Tx } else { Direction::Rx },
+                    data: data.to_vec(),
+                });
+            }
+            _ => {}
+        }
+        i += len;
+    }
+    Ok(records)
+}
+
+
+// 按记录时间间隔回放指定方向的数据，暂停期间不计入间隔
+// 每发送一条调用 on_progress(已发送, 总数, 是否暂停)，返回实际发送条数
+pub fn replay(
+    records: &[ReplayRecord],
+    option: &ReplayOption,
+    cancel: &AtomicBool,
+    pause: &AtomicBool,
+    mut send: impl FnMut(&[u8]) -> Result<()>,
+    mut on_progress: impl FnMut(usize, usize, bool),
+) -> Result<usize> {
+    let records = records.iter().filter(|v| v.dir == option.dir).collect::<Vec<_>>();
+    let total = records.len();
+    let Some(first) = records.first() else {
+        return Ok(0);
+    };
+    let start = Instant::now();
+    let mut paused = Duration::ZERO;
+
+    for (i, record) in records.iter().enumerate() {
+        let due = if option.speed > 0.0 {
+            Duration::from_nanos(((record.ts - first.ts).max(0) as f64 / option.speed) as u64)
+        } else {
+            Duration::ZERO
+        };
+        loop {
+            if cancel.load(Ordering::Relaxed) {
+                return Ok(i);
+            }
+            if pause.load(Ordering::Relaxed) {
+                let t = Instant::now();
+                on_progress(i, total, true);
+                while pause.load(Ordering::Relaxed) && !cancel.load(Ordering::Relaxed) {
+                    thread::sleep(Duration::from_millis(10));
+                }
+                paused += t.elapsed();
+                on_progress(i, total, false);
+                continue;
+            }
+            let elapsed = start.elapsed().saturating_sub(paused);
+            if elapsed >= due {
+                break;
+            }
+            // 分段等待，及时响应暂停与取消
+            thread::sleep((due - elapsed).min(Duration::from_millis(10)));
+        }
+        send(&record.data)?;
+        on_progress(i + 1, total, false);
+    }
+    Ok(total)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::BufWriter;
+    use chrono::{Local, TimeZone};
+    use crate::logger::{LogFormat, LogOption, SessionLogger};
+    use crate::pcapng::{PcapngWriter, LINKTYPE_USER0};
+
+    const T0: i64 = 1_700_000_000_000_000_000;
+
+    fn expect() -> Vec<ReplayRecord> {
+        vec![
+            ReplayRecord { ts: T0, dir: Direction::Tx, data: b"AT,\"x\"\r\n".to_vec() },
+            ReplayRecord { ts: T0 + 1_500, dir: Direction::Rx, data: vec![0x4F, 0x4B, 0xFF] },
+        ]
+    }
+
+    fn write_log(name: &str, format: LogFormat) -> String {
+        let dir = std::env::temp_dir().join(name);
+        fs::remove_dir_all(&dir).unwrap_or_default();
+        let option = LogOption { format, dir: dir.to_string_lossy().to_string(), ..Default::default() };
+        let mut logger = SessionLogger::open(option, "COM1", "r", encoding_rs::UTF_8, encoding_rs::UTF_8).unwrap();
+        for v in expect() {
+            logger.write(Local.timestamp_nanos(v.ts), v.dir, &v.data).unwrap();
+        }
+        logger.finish().unwrap().path
+    }
+
+    #[test]
+    fn test_read_capture() {
+        assert_eq!(read_capture(&write_log("multi_tools_replay_jsonl", LogFormat::Jsonl)).unwrap(), expect());
+        assert_eq!(read_capture(&write_log("multi_tools_replay_csv", LogFormat::Csv)).unwrap(), expect());
+
+        let path = std::env::temp_dir().join("multi_tools_replay.pcapng");
+        {
+            let file = BufWriter::new(fs::File::create(&path).unwrap());
+            let mut w = PcapngWriter::new(file, LINKTYPE_USER0, "COM1").unwrap();
+            for v in expect() {
+                w.write_packet(v.ts as u64, v.dir, &v.data).unwrap();
+            }
+        }
+        assert_eq!(read_capture(&path.to_string_lossy()).unwrap(), expect());
+    }
+
+    #[test]
+    fn test_read_pcapng_corrupt() {
+        let mut buf = Vec::new();
+        {
+            let mut w = PcapngWriter::new(&mut buf, LINKTYPE_USER0, "COM1").unwrap();
+            w.write_packet(T0 as u64, Direction::Tx, b"hello").unwrap();
+        }
+        assert_eq!(read_pcapng(&buf).unwrap().len(), 1);
+        // 任意位置截断都不会崩溃
+        for n in 0..buf.len() {
+            read_pcapng(&buf[..n]).ok();
+        }
+
+        let block = |block_type: u32, body: &[u8]| {
+            let len = (body.len() + 12) as u32;
+            [&block_type.to_le_bytes()[..], &len.to_le_bytes(), body, &len.to_le_bytes()].concat()
+        };
+        let shb = block(0x0A0D_0D0A, &0x1A2B_3C4Du32.to_le_bytes());
+        // 内容过短的 SHB、IDB、EPB
+        assert!(read_pcapng(&block(0x0A0D_0D0A, &[0x4D, 0x3C])).is_err());
+        assert!(read_pcapng(&[shb.clone(), block(1, &[0; 4])].concat()).is_err());
+        let idb = block(1, &[0; 8]);
+        assert!(read_pcapng(&[shb.clone(), idb.clone(), block(6, &[0; 12])].concat()).is_err());
+        // if_tsresol 选项值为空
+        let empty_resol = block(1, &[0, 0, 0, 0, 0, 0, 0, 0, 9, 0, 0, 0, 0, 0, 0, 0]);
+        let epb = block(6, &[0; 20]);
+        assert_eq!(read_pcapng(&[shb.clone(), empty_resol, epb].concat()).unwrap().len(), 1);
+        // 捕获长度超出块内容
+        let mut body = vec![0; 24];
+        body[12..16].copy_from_slice(&1000u32.to_le_bytes());
+        assert!(read_pcapng(&[shb.clone(), idb.clone(), block(6, &body)].concat()).is_err());
+        // 数据后没有选项的空间
+        let mut body = vec![0; 21];
+        body[12..16].copy_from_slice(&1u32.to_le_bytes());
+        assert_eq!(read_pcapng(&[shb, idb, block(6, &body)].concat()).unwrap()[0].data, vec![0]);
+    }
+
+    #[test]
+    fn test_replay_timing() {
+        let records = (0..4).map(|v| ReplayRecord { ts: T0 + v * 40_000_000, dir: Direction::Tx, data: vec![v as u8] }).collect::<Vec<_>>();
+        let cancel = AtomicBool::new(false);
+        let pause = AtomicBool::new(false);
+        let mut sent = Vec::new();
+
+        // 两倍速，总时长约 60ms
+        let start = Instant::now();
+        let n = replay(&records, &ReplayOption { dir: Direction::Tx, speed: 2.0 }, &cancel, &pause, |v| {
+            sent.push(v[0]);
+            Ok(())
+        }, |_, _, _| {}).unwrap();
+        assert_eq!((n, sent), (4, vec![0, 1, 2, 3]));
+        assert!(start.elapsed() >= Duration::from_millis(60));
+        assert!(start.elapsed() < Duration::from_millis(120));
+
+        // 只回放接收方向时没有数据
+        let option = ReplayOption { dir: Direction::Rx, speed: 0.0 };
+        assert_eq!(replay(&records, &option, &cancel, &pause, |_| Ok(()), |_, _, _| {}).unwrap(), 0);
+
+        // 发送两条后取消
+        let option = ReplayOption { dir: Direction::Tx, speed: 0.0 };
+        let n = replay(&records, &option, &cancel, &pause, |_| Ok(()), |i, _, _| {
+            if i == 2 {
+                cancel.store(true, Ordering::Relaxed);
+            }
+        }).unwrap();
+        assert_eq!(n, 2);
+    }
+}
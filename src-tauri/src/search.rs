@@ -0,0 +1,220 @@
+use anyhow::{format_err, Result};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use crate::codec::to_hex;
+use crate::parse::parse_hex;
+
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchKind {
+    Text,
+    Regex,
+    Hex,
+}
+
+// 搜索条件，文本与正则匹配解码后的文本，十六进制匹配原始字节
+#[derive(Clone, Debug, Deserialize)]
+pub struct SearchQuery {
+    pub kind: SearchKind,
+    pub pattern: String,
+    #[serde(default)]
+    pub ignore_case: bool,
+}
+
+// 搜索结果，匹配从记录 seq 的 start 处开始，到记录 end_seq 的 end 处结束，序号为记录的绝对序号
+// 文本匹配时 start/end 为文本的 UTF-16 下标，十六进制匹配时为字节下标
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct SearchHit {
+    pub seq: u64,
+    pub start: usize,
+    pub end_seq: u64,
+    pub end: usize,
+}
+
+#[derive(Clone, Debug)]
+pub enum Matcher {
+    Regex(Regex),
+    Hex(Vec<u8>),
+}
+
+impl Matcher {
+    pub fn new(query: &SearchQuery) -> Result<Self> {
+        match query.kind {
+            SearchKind::Hex => {
+                let bytes = parse_hex(&query.pattern).map_err(|e| format_err!("十六进制格式错误：{}", e[0].msg))?;
+                if bytes.is_empty() {
+                    return Err(format_err!("搜索内容为空"));
+                }
+                Ok(Matcher::Hex(bytes))
+            }
+            kind => {
+                if query.pattern.is_empty() {
+                    return Err(format_err!("搜索内容为空"));
+                }
+                let pattern = if kind == SearchKind::Text { regex::escape(&query.pattern) } else { query.pattern.clone() };
+                let regex = RegexBuilder::new(&pattern).case_insensitive(query.ignore_case).build()
+                    .map_err(|e| format_err!("正则表达式错误：{e}"))?;
+                Ok(Matcher::Regex(regex))
+            }
+        }
+    }
+
+    // 全部匹配位置，正则按顺序累计 UTF-16 长度，长文本也不必每次从头计算
+    pub fn find_all(&self, text: &str, bytes: &[u8]) -> Vec<(usize, usize)> {
+        match self {
+            Matcher::Regex(regex) => {
+                let (mut pos, mut units) = (0, 0);
+                regex.find_iter(text).map(|m| {
+                    units += text[pos..m.start()].encode_utf16().count();
+                    let start = units;
+                    units += m.as_str().encode_utf16().count();
+                    pos = m.end();
+                    (start, units)
+                }).collect()
+            }
+            Matcher::Hex(pattern) => {
+                let mut v = Vec::new();
+                let mut i = 0;
+                while i + pattern.len() <= bytes.len() {
+                    if bytes[i..].starts_with(pattern) {
+                        v.push((i, i + pattern.len()));
+                        i += pattern.len();
+                    } else {
+                        i += 1;
+                    }
+                }
+                v
+            }
+        }
+    }
+
+    // 正则能否匹配空内容，这类规则用于流式匹配时不会消耗数据
+    pub fn matches_empty(&self) -> bool {
+        match self {
+            Matcher::Regex(regex) => regex.is_match(""),
+            Matcher::Hex(_) => false,
+        }
+    }
+
+    pub fn is_match(&self, text: &str, bytes: &[u8]) -> bool {
+        match self {
+            Matcher::Regex(regex) => regex.is_match(text),
+            Matcher::Hex(pattern) => bytes.windows(pattern.len()).any(|v| v == pattern.as_slice()),
+        }
+    }
+}
+
+// 流式匹配，跨多次读取累积未被匹配消耗的内容，匹配过的内容不会重复匹配
+#[derive(Clone, Debug)]
+pub struct StreamMatcher {
+    matcher: Matcher,
+    text: String,
+    bytes: Vec<u8>,
+}
+
+// 匹配窗口上限，追加新数据前丢弃超出部分的旧内容
+const WINDOW: usize = 4096;
+
+impl StreamMatcher {
+    pub fn new(matcher: Matcher) -> Self {
+        Self { matcher, text: String::new(), bytes: Vec::new() }
+    }
+
+    // text 为 data 解码后的文本
+    pub fn push(&mut self, text: &str, data: &[u8]) {
+        match self.matcher {
+            Matcher::Regex(_) => {
+                if self.text.len() > WINDOW {
+                    let mut cut = self.text.len() - WINDOW;
+                    while !self.text.is_char_boundary(cut) {
+                        cut += 1;
+                    }
+                    self.text.drain(..cut);
+                }
+                self.text.push_str(text);
+            }
+            Matcher::Hex(_) => {
+                if self.bytes.len() > WINDOW {
+                    self.bytes.drain(..self.bytes.len() - WINDOW);
+                }
+                self.bytes.extend_from_slice(data);
+            }
+        }
+    }
+
+    // 取出下一个匹配，返回匹配的文本，十六进制匹配返回十六进制字符串
+    // 空匹配不消耗内容，跳过以免调用方反复取到同一位置
+    pub fn next_match(&mut self) -> Option<String> {
+        match &self.matcher {
+            Matcher::Regex(regex) => {
+                let m = regex.find_iter(&self.text).find(|m| !m.is_empty())?;
+                let v = m.as_str().to_string();
+                self.text.drain(..m.end());
+                Some(v)
+            }
+            Matcher::Hex(pattern) => {
+                let end = self.bytes.windows(pattern.len()).position(|v| v == pattern.as_slice())? + pattern.len();
+                self.bytes.drain(..end);
+                Some(to_hex(pattern))
+            }
+        }
+    }
+}
+
+// 显示过滤，invert 为 true 时只显示不匹配的记录
+#[derive(Clone, Debug)]
+pub struct DisplayFilter {
+    pub matcher: Matcher,
+    pub invert: bool,
+}
+
+impl DisplayFilter {
+    pub fn accept(&self, text: &str, bytes: &[u8]) -> bool {
+        self.matcher.is_match(text, bytes) != self.invert
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn query(kind: SearchKind, pattern: &str, ignore_case: bool) -> Matcher {
+        Matcher::new(&SearchQuery { kind, pattern: pattern.to_string(), ignore_case }).unwrap()
+    }
+
+    #[test]
+    fn test_matcher() {
+        let text = "中OK ok (1)";
+        assert_eq!(query(SearchKind::Text, "ok", true).find_all(text, &[]), vec![(1, 3), (4, 6)]);
+        assert_eq!(query(SearchKind::Text, "(1)", false).find_all(text, &[]), vec![(7, 10)]);
+        assert_eq!(query(SearchKind::Regex, r"[A-Z]+", false).find_all(text, &[]), vec![(1, 3)]);
+        assert_eq!(query(SearchKind::Hex, "AA 55", false).find_all("", &[0xAA, 0x55, 0xAA, 0xAA, 0x55]), vec![(0, 2), (3, 5)]);
+        assert!(Matcher::new(&SearchQuery { kind: SearchKind::Regex, pattern: "(".to_string(), ignore_case: false }).is_err());
+        assert!(Matcher::new(&SearchQuery { kind: SearchKind::Hex, pattern: "".to_string(), ignore_case: false }).is_err());
+
+        let filter = DisplayFilter { matcher: query(SearchKind::Hex, "55", false), invert: true };
+        assert!(filter.accept("", &[0x01]));
+        assert!(!filter.accept("", &[0x55]));
+    }
+
+    #[test]
+    fn test_stream_matcher_empty() {
+        for pattern in [".*", r"\d*", "^"] {
+            let mut m = StreamMatcher::new(query(SearchKind::Regex, pattern, false));
+            m.push("", b"");
+            assert_eq!(m.next_match(), None);
+        }
+
+        let mut m = StreamMatcher::new(query(SearchKind::Regex, r"\d*", false));
+        m.push("ab12c3", b"");
+        assert_eq!(m.next_match().as_deref(), Some("12"));
+        assert_eq!(m.next_match().as_deref(), Some("3"));
+        assert_eq!(m.next_match(), None);
+
+        let mut m = StreamMatcher::new(query(SearchKind::Regex, "^", false));
+        m.push("abc", b"");
+        assert_eq!(m.next_match(), None);
+    }
+}
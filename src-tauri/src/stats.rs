@@ -0,0 +1,172 @@
+use std::time::{Duration, Instant};
+use serde::Serialize;
+use crate::logger::Direction;
+
+
+// 速率，单位为每秒
+#[derive(Clone, Debug, Default, Serialize, PartialEq)]
+pub struct Rate {
+    // 最近一个统计周期
+    pub current: f64,
+    // 连接以来
+    pub average: f64,
+    pub peak: f64,
+}
+
+#[derive(Clone, Debug, Default, Serialize, PartialEq)]
+pub struct DirStats {
+    pub bytes: u64,
+    pub frames: u64,
+    pub bytes_rate: Rate,
+    pub frames_rate: Rate,
+}
+
+// 通过 stats_{id} 事件定期发往前端
+#[derive(Clone, Debug, Default, Serialize, PartialEq)]
+pub struct StatsReport {
+    pub rx: DirStats,
+    pub tx: DirStats,
+    pub frames: u64,
+    pub checksum_errors: u64,
+    pub framing_errors: u64,
+    // 连接时长，秒
+    pub uptime: f64,
+}
+
+#[derive(Debug, Default)]
+struct Counter {
+    bytes: u64,
+    frames: u64,
+    // 上次采样时的计数
+    last_bytes: u64,
+    last_frames: u64,
+    current_bytes: f64,
+    current_frames: f64,
+    peak_bytes: f64,
+    peak_frames: f64,
+}
+
+impl Counter {
+    fn sample(&mut self, elapsed: Duration) {
+        let secs = elapsed.as_secs_f64();
+        if secs <= 0.0 {
+            return;
+        }
+        self.current_bytes = (self.bytes - self.last_bytes) as f64 / secs;
+        self.current_frames = (self.frames - self.last_frames) as f64 / secs;
+        self.peak_bytes = self.peak_bytes.max(self.current_bytes);
+        self.peak_frames = self.peak_frames.max(self.current_frames);
+        self.last_bytes = self.bytes;
+        self.last_frames = self.frames;
+    }
+
+    fn report(&self, uptime: f64) -> DirStats {
+        let average = |v: u64| if uptime > 0.0 { v as f64 / uptime } else { 0.0 };
+        DirStats {
+            bytes: self.bytes,
+            frames: self.frames,
+            bytes_rate: Rate { current: self.current_bytes, average: average(self.bytes), peak: self.peak_bytes },
+            frames_rate: Rate { current: self.current_frames, average: average(self.frames), peak: self.peak_frames },
+        }
+    }
+}
+
+// 会话收发统计，计数与前端可清零的收发计数相互独立
+#[derive(Debug)]
+pub struct SessionStats {
+    start: Instant,
+    last_sample: Instant,
+    rx: Counter,
+    tx: Counter,
+    checksum_errors: u64,
+    framing_errors: u64,
+}
+
+impl SessionStats {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            start: now,
+            last_sample: now,
+            rx: Counter::default(),
+            tx: Counter::default(),
+            checksum_errors: 0,
+            framing_errors: 0,
+        }
+    }
+
+    fn counter(&mut self, dir: Direction) -> &mut Counter {
+        match dir {
+            Direction::Rx => &mut self.rx,
+            Direction::Tx => &mut self.tx,
+        }
+    }
+
+    pub fn add_bytes(&mut self, dir: Direction, len: usize) {
+        self.counter(dir).bytes += len as u64;
+    }
+
+    pub fn add_frame(&mut self, dir: Direction) {
+        self.counter(dir).frames += 1;
+    }
+
+    pub fn add_checksum_error(&mut self) {
+        self.checksum_errors += 1;
+    }
+
+    pub fn add_framing_error(&mut self) {
+        self.framing_errors += 1;
+    }
+
+    // 以上次采样到 now 为一个周期计算当前速率
+    pub fn report(&mut self, now: Instant) -> StatsReport {
+        let elapsed = now.duration_since(self.last_sample);
+        self.rx.sample(elapsed);
+        self.tx.sample(elapsed);
+        self.last_sample = now;
+        let uptime = now.duration_since(self.start).as_secs_f64();
+        StatsReport {
+            rx: self.rx.report(uptime),
+            tx: self.tx.report(uptime),
+            frames: self.rx.frames + self.tx.frames,
+            checksum_errors: self.checksum_errors,
+            framing_errors: self.framing_errors,
+            uptime,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_stats_report() {
+        let start = Instant::now();
+        let mut stats = SessionStats::new(start);
+        stats.add_bytes(Direction::Rx, 3000);
+        stats.add_frame(Direction::Rx);
+        stats.add_frame(Direction::Rx);
+        stats.add_bytes(Direction::Tx, 10);
+        stats.add_frame(Direction::Tx);
+        let report = stats.report(start + Duration::from_secs(1));
+        assert_eq!(report.rx.bytes_rate, Rate { current: 3000.0, average: 3000.0, peak: 3000.0 });
+        assert_eq!(report.frames, 3);
+
+        stats.add_bytes(Direction::Rx, 1000);
+        stats.add_checksum_error();
+        let report = stats.report(start + Duration::from_secs(2));
+        assert_eq!(report.rx.bytes_rate, Rate { current: 1000.0, average: 2000.0, peak: 3000.0 });
+        assert_eq!(report.tx.frames_rate.current, 0.0);
+        assert_eq!((report.rx.bytes, report.checksum_errors, report.uptime), (4000, 1, 2.0));
+    }
+
+    #[test]
+    fn test_counter_u64() {
+        let mut stats = SessionStats::new(Instant::now());
+        for _ in 0..3 {
+            stats.add_bytes(Direction::Rx, u32::MAX as usize);
+        }
+        assert_eq!(stats.report(Instant::now()).rx.bytes, 3 * u32::MAX as u64);
+    }
+}
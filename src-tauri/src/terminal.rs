@@ -0,0 +1,854 @@
+use std::collections::VecDeque;
+use encoding_rs::Encoding;
+use serde::{Deserialize, Serialize};
+use vte::{Params, Parser, Perform};
+use crate::codec::StreamDecoder;
+
+
+#[derive(Clone, Copy, Debug, Default, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Color {
+    #[default]
+    Default,
+    // 0-7 标准色，8-15 高亮色，16-255 为 256 色
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize, PartialEq, Eq)]
+pub struct Style {
+    pub fg: Color,
+    pub bg: Color,
+    pub bold: bool,
+    pub underline: bool,
+    pub inverse: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Cell {
+    ch: char,
+    style: Style,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self { ch: ' ', style: Style::default() }
+    }
+}
+
+// 双宽字符右半部分的占位，不单独显示
+const WIDE_TAIL: char = '\0';
+
+// 字符占用的列数，中日韩文字、全角符号与常见 emoji 占两列
+fn char_width(c: char) -> usize {
+    match c as u32 {
+        0x1100..=0x115F | 0x2E80..=0x303E | 0x3041..=0x33FF | 0x3400..=0x4DBF | 0x4E00..=0x9FFF
+        | 0xA000..=0xA4CF | 0xAC00..=0xD7A3 | 0xF900..=0xFAFF | 0xFE30..=0xFE4F | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6 | 0x1F300..=0x1F64F | 0x1F900..=0x1F9FF | 0x20000..=0x3FFFD => 2,
+        _ => 1,
+    }
+}
+
+// 同一样式的连续字符
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct Span {
+    pub text: String,
+    pub style: Style,
+}
+
+// 屏幕变化，通过 term_{id} 事件发往前端
+// full 为 true 时 lines 包含全部行，前端整体替换
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct ScreenDiff {
+    pub cols: usize,
+    pub rows: usize,
+    pub full: bool,
+    // 上次发送后滚出屏幕进入回滚区的行
+    pub scrolled: Vec<Vec<Span>>,
+    // (行号, 内容)
+    pub lines: Vec<(usize, Vec<Span>)>,
+    pub cursor: (usize, usize),
+    pub cursor_visible: bool,
+    pub bell: bool,
+    // 回滚区已清空，前端丢弃已有的回滚内容
+    pub clear_scrollback: bool,
+}
+
+// 终端快照，包含回滚区与当前屏幕
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct TerminalSnapshot {
+    pub scrollback: Vec<Vec<Span>>,
+    pub screen: ScreenDiff,
+}
+
+// 终端配置
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct TerminalOption {
+    pub cols: usize,
+    pub rows: usize,
+    // 回滚区保留的行数
+    pub scrollback: usize,
+}
+
+impl Default for TerminalOption {
+    fn default() -> Self {
+        Self { cols: 80, rows: 24, scrollback: 1000 }
+    }
+}
+
+fn spans(line: &[Cell]) -> Vec<Span> {
+    // 行尾空白不发送
+    let end = line.iter().rposition(|v| *v != Cell::default()).map(|v| v + 1).unwrap_or(0);
+    let mut spans: Vec<Span> = Vec::new();
+    for cell in line[..end].iter().filter(|v| v.ch != WIDE_TAIL) {
+        match spans.last_mut() {
+            Some(span) if span.style == cell.style => span.text.push(cell.ch),
+            _ => spans.push(Span { text: cell.ch.to_string(), style: cell.style }),
+        }
+    }
+    spans
+}
+
+#[derive(Debug)]
+struct Screen {
+    cols: usize,
+    rows: usize,
+    grid: Vec<Vec<Cell>>,
+    scrollback: VecDeque<Vec<Cell>>,
+    scrollback_limit: usize,
+    // 上次发送后滚出屏幕的行
+    scrolled: Vec<Vec<Cell>>,
+    dirty: Vec<bool>,
+    full: bool,
+    row: usize,
+    col: usize,
+    // 写满最后一列后，下一个字符才换行
+    wrap_pending: bool,
+    saved: (usize, usize, Style),
+    style: Style,
+    // 滚动区域，含两端
+    top: usize,
+    bottom: usize,
+    cursor_visible: bool,
+    // 光标键应用模式，影响方向键的编码
+    app_cursor: bool,
+    bell: bool,
+    // 上次发送时的光标，只有光标移动时同样发送
+    last_cursor: Option<(usize, usize, bool)>,
+    // 需要回复给设备的数据，如光标位置报告
+    responses: Vec<u8>,
+    // 上次发送后回滚区被清空
+    scrollback_cleared: bool,
+    // 切换到备用屏幕时保存的主屏内容、光标与样式
+    main_screen: Option<(Vec<Vec<Cell>>, usize, usize, Style)>,
+}
+
+impl Screen {
+    fn new(option: &TerminalOption) -> Self {
+        let cols = option.cols.max(1);
+        let rows = option.rows.max(1);
+        Self {
+            cols,
+            rows,
+            grid: vec![vec![Cell::default(); cols]; rows],
+            scrollback: VecDeque::new(),
+            scrollback_limit: option.scrollback,
+            scrolled: Vec::new(),
+            dirty: vec![false; rows],
+            full: true,
+            row: 0,
+            col: 0,
+            wrap_pending: false,
+            saved: (0, 0, Style::default()),
+            style: Style::default(),
+            top: 0,
+            bottom: rows - 1,
+            cursor_visible: true,
+            app_cursor: false,
+            bell: false,
+            last_cursor: None,
+            responses: Vec::new(),
+            scrollback_cleared: false,
+            main_screen: None,
+        }
+    }
+
+    fn blank(&self) -> Cell {
+        Cell { ch: ' ', style: Style { bg: self.style.bg, ..Style::default() } }
+    }
+
+    fn resize(&mut self, cols: usize, rows: usize) {
+        let cols = cols.max(1);
+        let rows = rows.max(1);
+        for line in self.grid.iter_mut() {
+            line.resize(cols, Cell::default());
+        }
+        // 行数减少时把顶部的行移入回滚区
+        while self.grid.len() > rows {
+            let line = self.grid.remove(0);
+            self.push_scrollback(line);
+            self.row = self.row.saturating_sub(1);
+        }
+        self.grid.resize(rows, vec![Cell::default(); cols]);
+        self.cols = cols;
+        self.rows = rows;
+        self.top = 0;
+        self.bottom = rows - 1;
+        self.row = self.row.min(rows - 1);
+        self.col = self.col.min(cols - 1);
+        self.wrap_pending = false;
+        self.dirty = vec![false; rows];
+        self.full = true;
+    }
+
+    fn push_scrollback(&mut self, line: Vec<Cell>) {
+        if self.scrollback_limit == 0 {
+            return;
+        }
+        if self.scrollback.len() >= self.scrollback_limit {
+            self.scrollback.pop_front();
+        }
+        self.scrollback.push_back(line.clone());
+        self.scrolled.push(line);
+    }
+
+    fn mark(&mut self, row: usize) {
+        if let Some(v) = self.dirty.get_mut(row) {
+            *v = true;
+        }
+    }
+
+    fn mark_range(&mut self, start: usize, end: usize) {
+        for row in start..=end.min(self.rows - 1) {
+            self.mark(row);
+        }
+    }
+
+    // 滚动区域上移 n 行，区域从屏幕顶部开始时移出的行进入回滚区，备用屏幕除外
+    fn scroll_up(&mut self, n: usize) {
+        for _ in 0..n.min(self.bottom - self.top + 1) {
+            let line = self.grid.remove(self.top);
+            if self.top == 0 && self.main_screen.is_none() {
+                self.push_scrollback(line);
+            }
+            let blank = vec![self.blank(); self.cols];
+            self.grid.insert(self.bottom, blank);
+        }
+        self.mark_range(self.top, self.bottom);
+    }
+
+    fn scroll_down(&mut self, n: usize) {
+        for _ in 0..n.min(self.bottom - self.top + 1) {
+            self.grid.remove(self.bottom);
+            let blank = vec![self.blank(); self.cols];
+            self.grid.insert(self.top, blank);
+        }
+        self.mark_range(self.top, self.bottom);
+    }
+
+    fn linefeed(&mut self) {
+        self.wrap_pending = false;
+        if self.row == self.bottom {
+            self.scroll_up(1);
+        } else if self.row + 1 < self.rows {
+            self.row += 1;
+        }
+    }
+
+    fn reverse_index(&mut self) {
+        self.wrap_pending = false;
+        if self.row == self.top {
+            self.scroll_down(1);
+        } else {
+            self.row = self.row.saturating_sub(1);
+        }
+    }
+
+    fn goto(&mut self, row: usize, col: usize) {
+        self.row = row.min(self.rows - 1);
+        self.col = col.min(self.cols - 1);
+        self.wrap_pending = false;
+    }
+
+    fn erase(&mut self, row: usize, start: usize, end: usize) {
+        let blank = self.blank();
+        let end = end.min(self.cols);
+        if start < end {
+            self.grid[row][start..end].fill(blank);
+        }
+        self.mark(row);
+    }
+
+    fn erase_display(&mut self, mode: u16) {
+        match mode {
+            0 => {
+                self.erase(self.row, self.col, self.cols);
+                for row in self.row + 1..self.rows {
+                    self.erase(row, 0, self.cols);
+                }
+            }
+            1 => {
+                for row in 0..self.row {
+                    self.erase(row, 0, self.cols);
+                }
+                self.erase(self.row, 0, self.col + 1);
+            }
+            2 => {
+                for row in 0..self.rows {
+                    self.erase(row, 0, self.cols);
+                }
+            }
+            3 => {
+                self.scrollback.clear();
+                self.scrolled.clear();
+                self.scrollback_cleared = true;
+            }
+            _ => {}
+        }
+    }
+
+    fn erase_line(&mut self, mode: u16) {
+        match mode {
+            0 => self.erase(self.row, self.col, self.cols),
+            1 => self.erase(self.row, 0, self.col + 1),
+            2 => self.erase(self.row, 0, self.cols),
+            _ => {}
+        }
+    }
+
+    fn insert_lines(&mut self, n: usize) {
+        if self.row < self.top || self.row > self.bottom {
+            return;
+        }
+        for _ in 0..n.min(self.bottom - self.row + 1) {
+            self.grid.remove(self.bottom);
+            let blank = vec![self.blank(); self.cols];
+            self.grid.insert(self.row, blank);
+        }
+        self.mark_range(self.row, self.bottom);
+    }
+
+    fn delete_lines(&mut self, n: usize) {
+        if self.row < self.top || self.row > self.bottom {
+            return;
+        }
+        for _ in 0..n.min(self.bottom - self.row + 1) {
+            self.grid.remove(self.row);
+            let blank = vec![self.blank(); self.cols];
+            self.grid.insert(self.bottom, blank);
+        }
+        self.mark_range(self.row, self.bottom);
+    }
+
+    fn insert_chars(&mut self, n: usize) {
+        let blank = self.blank();
+        let line = &mut self.grid[self.row];
+        for _ in 0..n.min(self.cols - self.col) {
+            line.pop();
+            line.insert(self.col, blank);
+        }
+        self.mark(self.row);
+    }
+
+    fn delete_chars(&mut self, n: usize) {
+        let blank = self.blank();
+        let line = &mut self.grid[self.row];
+        for _ in 0..n.min(self.cols - self.col) {
+            line.remove(self.col);
+            line.push(blank);
+        }
+        self.mark(self.row);
+    }
+
+    fn sgr(&mut self, params: &[u16]) {
+        if params.is_empty() {
+            self.style = Style::default();
+            return;
+        }
+        let mut i = 0;
+        while i < params.len() {
+            match params[i] {
+                0 => self.style = Style::default(),
+                1 => self.style.bold = true,
+                4 => self.style.underline = true,
+                7 => self.style.inverse = true,
+                22 => self.style.bold = false,
+                24 => self.style.underline = false,
+                27 => self.style.inverse = false,
+                v @ 30..=37 => self.style.fg = Color::Indexed((v - 30) as u8),
+                39 => self.style.fg = Color::Default,
+                v @ 40..=47 => self.style.bg = Color::Indexed((v - 40) as u8),
+                49 => self.style.bg = Color::Default,
+                v @ 90..=97 => self.style.fg = Color::Indexed((v - 90 + 8) as u8),
+                v @ 100..=107 => self.style.bg = Color::Indexed((v - 100 + 8) as u8),
+                v @ (38 | 48) => {
+                    let color = match params.get(i + 1) {
+                        Some(5) => {
+                            i += 2;
+                            params.get(i).map(|v| Color::Indexed(*v as u8))
+                        }
+                        Some(2) => {
+                            i += 4;
+                            match params.get(i - 2..=i) {
+                                Some([r, g, b]) => Some(Color::Rgb(*r as u8, *g as u8, *b as u8)),
+                                _ => None,
+                            }
+                        }
+                        _ => None,
+                    };
+                    if let Some(color) = color {
+                        if v == 38 { self.style.fg = color } else { self.style.bg = color }
+                    }
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+    }
+
+    fn set_mode(&mut self, params: &[u16], private: bool, on: bool) {
+        if !private {
+            return;
+        }
+        for v in params {
+            match v {
+                1 => self.app_cursor = on,
+                25 => self.cursor_visible = on,
+                // 备用屏幕，进入时保存主屏，退出时恢复
+                47 | 1047 | 1049 => self.alternate_screen(on),
+                _ => {}
+            }
+        }
+    }
+
+    fn alternate_screen(&mut self, on: bool) {
+        if on {
+            if self.main_screen.is_none() {
+                self.main_screen = Some((self.grid.clone(), self.row, self.col, self.style));
+                self.erase_display(2);
+                self.goto(0, 0);
+            }
+        } else if let Some((mut grid, row, col, style)) = self.main_screen.take() {
+            // 备用屏幕期间可能改变过大小
+            for line in grid.iter_mut() {
+                line.resize(self.cols, Cell::default());
+            }
+            grid.resize(self.rows, vec![Cell::default(); self.cols]);
+            self.grid = grid;
+            self.goto(row, col);
+            self.style = style;
+            self.full = true;
+        }
+    }
+
+    // 覆盖双宽字符的一半时，另一半改为空白
+    fn split_wide(&mut self, row: usize, col: usize) {
+        let line = &mut self.grid[row];
+        if line[col].ch == WIDE_TAIL && col > 0 {
+            line[col - 1].ch = ' ';
+        }
+        if line.get(col + 1).is_some_and(|v| v.ch == WIDE_TAIL) {
+            line[col + 1].ch = ' ';
+        }
+    }
+
+    fn take_diff(&mut self) -> Option<ScreenDiff> {
+        let cursor = (self.row, self.col, self.cursor_visible);
+        let changed = self.full || !self.scrolled.is_empty() || self.bell || self.scrollback_cleared || self.dirty.iter().any(|v| *v)
+            || self.last_cursor != Some(cursor);
+        if !changed {
+            return None;
+        }
+        self.last_cursor = Some(cursor);
+        let diff = ScreenDiff {
+            cols: self.cols,
+            rows: self.rows,
+            full: self.full,
+            scrolled: self.scrolled.drain(..).map(|v| spans(&v)).collect(),
+            lines: (0..self.rows).filter(|v| self.full || self.dirty[*v]).map(|v| (v, spans(&self.grid[v]))).collect(),
+            cursor: (self.row, self.col),
+            cursor_visible: self.cursor_visible,
+            bell: self.bell,
+            clear_scrollback: self.scrollback_cleared,
+        };
+        self.full = false;
+        self.bell = false;
+        self.scrollback_cleared = false;
+        self.dirty.fill(false);
+        Some(diff)
+    }
+}
+
+impl Perform for Screen {
+    fn print(&mut self, c: char) {
+        // 只有一列时双宽字符按单宽处理
+        let width = if self.cols > 1 { char_width(c) } else { 1 };
+        if self.wrap_pending {
+            self.col = 0;
+            self.linefeed();
+        }
+        // 行尾放不下双宽字符时留空并换行
+        if self.col + width > self.cols {
+            self.split_wide(self.row, self.col);
+            self.grid[self.row][self.col] = self.blank();
+            self.mark(self.row);
+            self.col = 0;
+            self.linefeed();
+        }
+        self.split_wide(self.row, self.col);
+        self.grid[self.row][self.col] = Cell { ch: c, style: self.style };
+        if width == 2 {
+            self.split_wide(self.row, self.col + 1);
+            self.grid[self.row][self.col + 1] = Cell { ch: WIDE_TAIL, style: self.style };
+        }
+        self.mark(self.row);
+        if self.col + width < self.cols {
+            self.col += width;
+        } else {
+            self.col = self.cols - 1;
+            self.wrap_pending = true;
+        }
+    }
+
+    fn execute(&mut self, byte: u8) {
+        match byte {
+            0x07 => self.bell = true,
+            0x08 => {
+                self.col = self.col.saturating_sub(1);
+                self.wrap_pending = false;
+            }
+            b'\t' => {
+                let col = (self.col / 8 + 1) * 8;
+                self.goto(self.row, col);
+            }
+            b'\n' | 0x0B | 0x0C => self.linefeed(),
+            b'\r' => {
+                self.col = 0;
+                self.wrap_pending = false;
+            }
+            _ => {}
+        }
+    }
+
+    fn csi_dispatch(&mut self, params: &Params, intermediates: &[u8], ignore: bool, action: char) {
+        if ignore {
+            return;
+        }
+        let params = params.iter().flat_map(|v| v.iter().copied()).collect::<Vec<u16>>();
+        // 缺省或为 0 的参数按 1 处理
+        let n = params.first().copied().filter(|v| *v > 0).unwrap_or(1) as usize;
+        let arg = |i: usize| params.get(i).copied().filter(|v| *v > 0).unwrap_or(1) as usize;
+        let private = intermediates.first() == Some(&b'?');
+        match action {
+            'A' => self.goto(self.row.saturating_sub(n), self.col),
+            'B' | 'e' => self.goto(self.row + n, self.col),
+            'C' | 'a' => self.goto(self.row, self.col + n),
+            'D' => self.goto(self.row, self.col.saturating_sub(n)),
+            'E' => self.goto(self.row + n, 0),
+            'F' => self.goto(self.row.saturating_sub(n), 0),
+            'G' | '`' => self.goto(self.row, n - 1),
+            'd' => self.goto(n - 1, self.col),
+            'H' | 'f' => self.goto(arg(0) - 1, arg(1) - 1),
+            'J' => self.erase_display(params.first().copied().unwrap_or(0)),
+            'K' => self.erase_line(params.first().copied().unwrap_or(0)),
+            'L' => self.insert_lines(n),
+            'M' => self.delete_lines(n),
+            '@' => self.insert_chars(n),
+            'P' => self.delete_chars(n),
+            'X' => self.erase(self.row, self.col, self.col + n),
+            'S' => self.scroll_up(n),
+            'T' => self.scroll_down(n),
+            'm' if intermediates.is_empty() => self.sgr(&params),
+            'r' => {
+                let top = arg(0) - 1;
+                let bottom = params.get(1).copied().filter(|v| *v > 0).map(|v| v as usize).unwrap_or(self.rows).min(self.rows) - 1;
+                if top < bottom {
+                    self.top = top;
+                    self.bottom = bottom;
+                    self.goto(0, 0);
+                }
+            }
+            's' => self.saved = (self.row, self.col, self.style),
+            'u' => {
+                let (row, col, style) = self.saved;
+                self.goto(row, col);
+                self.style = style;
+            }
+            'n' if params.first() == Some(&6) => {
+                self.responses.extend(format!("\x1b[{};{}R", self.row + 1, self.col + 1).as_bytes());
+            }
+            'n' if params.first() == Some(&5) => self.responses.extend(b"\x1b[0n"),
+            'c' if !private => self.responses.extend(b"\x1b[?1;2c"),
+            'h' => self.set_mode(&params, private, true),
+            'l' => self.set_mode(&params, private, false),
+            _ => {}
+        }
+    }
+
+    fn esc_dispatch(&mut self, intermediates: &[u8], ignore: bool, byte: u8) {
+        if ignore || !intermediates.is_empty() {
+            return;
+        }
+        match byte {
+            b'7' => self.saved = (self.row, self.col, self.style),
+            b'8' => {
+                let (row, col, style) = self.saved;
+                self.goto(row, col);
+                self.style = style;
+            }
+            b'D' => self.linefeed(),
+            b'E' => {
+                self.col = 0;
+                self.linefeed();
+            }
+            b'M' => self.reverse_index(),
+            b'c' => {
+                let option = TerminalOption { cols: self.cols, rows: self.rows, scrollback: self.scrollback_limit };
+                let scrollback = std::mem::take(&mut self.scrollback);
+                *self = Screen::new(&option);
+                self.scrollback = scrollback;
+            }
+            _ => {}
+        }
+    }
+}
+
+// 终端模拟会话，按 VT100/xterm 控制序列维护屏幕与回滚区
+pub struct Terminal {
+    parser: Parser,
+    screen: Screen,
+    // 接收数据按会话显示编码解码后再交给解析器
+    decoder: StreamDecoder,
+}
+
+impl std::fmt::Debug for Terminal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Terminal").field("screen", &self.screen).finish()
+    }
+}
+
+impl Terminal {
+    // code 为接收数据的编码
+    pub fn new(option: &TerminalOption, code: &'static Encoding) -> Self {
+        Self { parser: Parser::new(), screen: Screen::new(option), decoder: StreamDecoder::new(code) }
+    }
+
+    // 切换接收数据的编码，未解码完的字节丢弃
+    pub fn set_code(&mut self, code: &'static Encoding) {
+        self.decoder = StreamDecoder::new(code);
+    }
+
+    // 输入接收数据，解码后的文本按 UTF-8 交给解析器，控制序列在各编码中均为 ASCII
+    pub fn feed(&mut self, data: &[u8]) {
+        let text = self.decoder.decode(data);
+        for v in text.as_bytes() {
+            self.parser.advance(&mut self.screen, *v);
+        }
+    }
+
+    pub fn resize(&mut self, cols: usize, rows: usize) {
+        self.screen.resize(cols, rows);
+    }
+
+    // 取出需要回复给设备的数据
+    pub fn take_responses(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.screen.responses)
+    }
+
+    // 取出上次之后的屏幕变化，没有变化时返回 None
+    pub fn take_diff(&mut self) -> Option<ScreenDiff> {
+        self.screen.take_diff()
+    }
+
+    // 完整快照，之后的变化从此处开始
+    pub fn snapshot(&mut self) -> TerminalSnapshot {
+        self.screen.full = true;
+        self.screen.scrolled.clear();
+        self.screen.scrollback_cleared = false;
+        TerminalSnapshot {
+            scrollback: self.screen.scrollback.iter().map(|v| spans(v)).collect(),
+            screen: self.screen.take_diff().unwrap(),
+        }
+    }
+
+    pub fn app_cursor(&self) -> bool {
+        self.screen.app_cursor
+    }
+}
+
+// 前端按键，key 为 KeyboardEvent.key
+#[derive(Clone, Debug, Deserialize)]
+pub struct KeyInput {
+    pub key: String,
+    #[serde(default)]
+    pub ctrl: bool,
+    #[serde(default)]
+    pub alt: bool,
+    #[serde(default)]
+    pub shift: bool,
+}
+
+// 按键编码为发送给设备的原始字节，无法识别的按键返回空
+pub fn encode_key(input: &KeyInput, app_cursor: bool, code: &'static encoding_rs::Encoding) -> Vec<u8> {
+    let cursor = |c: char| if app_cursor { format!("\x1bO{c}") } else { format!("\x1b[{c}") };
+    let seq = match input.key.as_str() {
+        "Enter" => "\r".to_string(),
+        "Backspace" => "\x7f".to_string(),
+        "Tab" if input.shift => "\x1b[Z".to_string(),
+        "Tab" => "\t".to_string(),
+        "Escape" => "\x1b".to_string(),
+        "ArrowUp" => cursor('A'),
+        "ArrowDown" => cursor('B'),
+        "ArrowRight" => cursor('C'),
+        "ArrowLeft" => cursor('D'),
+        "Home" => cursor('H'),
+        "End" => cursor('F'),
+        "Insert" => "\x1b[2~".to_string(),
+        "Delete" => "\x1b[3~".to_string(),
+        "PageUp" => "\x1b[5~".to_string(),
+        "PageDown" => "\x1b[6~".to_string(),
+        "F1" => "\x1bOP".to_string(),
+        "F2" => "\x1bOQ".to_string(),
+        "F3" => "\x1bOR".to_string(),
+        "F4" => "\x1bOS".to_string(),
+        key if key.starts_with('F') && key.len() > 1 => {
+            let n = match &key[1..] {
+                "5" => 15,
+                "6" => 17,
+                "7" => 18,
+                "8" => 19,
+                "9" => 20,
+                "10" => 21,
+                "11" => 23,
+                "12" => 24,
+                _ => return Vec::new(),
+            };
+            format!("\x1b[{n}~")
+        }
+        key => {
+            let mut chars = key.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) if input.ctrl => match c.to_ascii_uppercase() {
+                    c @ '@'..='_' => ((c as u8 - b'@') as char).to_string(),
+                    ' ' => "\0".to_string(),
+                    '?' => "\x7f".to_string(),
+                    _ => return Vec::new(),
+                },
+                (Some(_), None) => key.to_string(),
+                _ => return Vec::new(),
+            }
+        }
+    };
+    let mut bytes = if input.alt { vec![0x1b] } else { Vec::new() };
+    bytes.extend_from_slice(&code.encode(&seq).0);
+    bytes
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn text(diff: &ScreenDiff, row: usize) -> String {
+        diff.lines.iter().find(|v| v.0 == row).map(|v| v.1.iter().map(|v| v.text.as_str()).collect()).unwrap_or_default()
+    }
+
+    #[test]
+    fn test_terminal_screen() {
+        let mut term = Terminal::new(&TerminalOption { cols: 10, rows: 3, scrollback: 10 }, encoding_rs::UTF_8);
+        term.feed(b"hello\r\n\x1b[31mred\x1b[0m!");
+        let diff = term.take_diff().unwrap();
+        assert!(diff.full);
+        assert_eq!((text(&diff, 0), text(&diff, 1)), ("hello".to_string(), "red!".to_string()));
+        assert_eq!(diff.lines[1].1[0].style.fg, Color::Indexed(1));
+        assert_eq!(diff.cursor, (1, 4));
+        assert!(term.take_diff().is_none());
+
+        // 清屏并定位后只发送变化的行
+        term.feed(b"\x1b[2J\x1b[3;2Hx\x1b[1;1H\x1b[K");
+        let diff = term.take_diff().unwrap();
+        assert!(!diff.full);
+        assert_eq!(diff.lines.iter().map(|v| v.0).collect::<Vec<_>>(), vec![0, 1, 2]);
+        assert_eq!(text(&diff, 2), " x");
+
+        // 超出屏幕的行进入回滚区
+        term.feed(b"\x1b[3;1H\n\n");
+        let diff = term.take_diff().unwrap();
+        assert_eq!(diff.scrolled.len(), 2);
+        assert_eq!(term.snapshot().scrollback.len(), 2);
+
+        term.feed(b"\x1b[6n");
+        assert_eq!(term.take_responses(), b"\x1b[3;1R");
+    }
+
+    #[test]
+    fn test_terminal_alternate() {
+        let mut term = Terminal::new(&TerminalOption { cols: 10, rows: 3, scrollback: 10 }, encoding_rs::UTF_8);
+        term.feed(b"$ ls\r\na b\r\n$ ");
+        term.take_diff();
+        term.feed(b"\x1b[?1049h\x1b[31mvi\n\n\n\n");
+        let diff = term.take_diff().unwrap();
+        assert_eq!(text(&diff, 0), "");
+        // 备用屏幕滚出的行不进入回滚区
+        assert!(diff.scrolled.is_empty());
+
+        term.feed(b"\x1b[?1049l!");
+        let diff = term.take_diff().unwrap();
+        assert!(diff.full);
+        assert_eq!((0..3).map(|v| text(&diff, v)).collect::<Vec<_>>(), vec!["$ ls", "a b", "$ !"]);
+        assert_eq!(diff.lines[2].1.iter().map(|v| v.style.fg).collect::<Vec<_>>(), vec![Color::Default]);
+        assert_eq!(diff.cursor, (2, 3));
+
+        // 清空回滚区
+        term.feed(b"\n\n\x1b[3J");
+        let diff = term.take_diff().unwrap();
+        assert!(diff.clear_scrollback && diff.scrolled.is_empty());
+        assert!(term.snapshot().scrollback.is_empty());
+    }
+
+    #[test]
+    fn test_terminal_wide() {
+        let mut term = Terminal::new(&TerminalOption { cols: 5, rows: 3, scrollback: 10 }, encoding_rs::GBK);
+        // GBK 编码的“中文”，跨两次读取
+        term.feed(&[b'a', b'b', 0xD6]);
+        term.feed(&[0xD0, 0xCE, 0xC4]);
+        let diff = term.take_diff().unwrap();
+        // 第二个字符在行尾放不下，换到下一行
+        assert_eq!((text(&diff, 0), text(&diff, 1)), ("ab中".to_string(), "文".to_string()));
+        assert_eq!(diff.cursor, (1, 2));
+
+        // 覆盖双宽字符的右半部分
+        term.feed(b"\x1b[1;4Hx");
+        let diff = term.take_diff().unwrap();
+        assert_eq!(text(&diff, 0), "ab x");
+        assert_eq!(diff.cursor, (0, 4));
+
+        // 切换编码后按新编码解码
+        term.set_code(encoding_rs::UTF_8);
+        term.feed("\x1b[3;1H字".as_bytes());
+        assert_eq!(text(&term.take_diff().unwrap(), 2), "字");
+
+        // 写满一行后光标停在最后一列，下一个字符换行
+        let mut term = Terminal::new(&TerminalOption { cols: 4, rows: 3, scrollback: 10 }, encoding_rs::UTF_8);
+        term.feed("文字".as_bytes());
+        let diff = term.take_diff().unwrap();
+        assert_eq!((text(&diff, 0), diff.cursor), ("文字".to_string(), (0, 3)));
+        term.feed("文".as_bytes());
+        let diff = term.take_diff().unwrap();
+        assert_eq!((text(&diff, 1), diff.cursor), ("文".to_string(), (1, 2)));
+    }
+
+    #[test]
+    fn test_encode_key() {
+        let key = |key: &str, ctrl: bool| KeyInput { key: key.to_string(), ctrl, alt: false, shift: false };
+        let utf8 = encoding_rs::UTF_8;
+        assert_eq!(encode_key(&key("ArrowUp", false), false, utf8), b"\x1b[A");
+        assert_eq!(encode_key(&key("ArrowUp", false), true, utf8), b"\x1bOA");
+        assert_eq!(encode_key(&key("c", true), false, utf8), [0x03]);
+        assert_eq!(encode_key(&key("Enter", false), false, utf8), b"\r");
+        assert_eq!(encode_key(&key("F5", false), false, utf8), b"\x1b[15~");
+        assert_eq!(encode_key(&key("啊", false), false, encoding_rs::GBK), [0xB0, 0xA1]);
+        assert!(encode_key(&key("Shift", false), false, utf8).is_empty());
+    }
+}
@@ -0,0 +1,219 @@
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+use anyhow::{Context, Result};
+use multi_tools_serialport::serialport::SerialPort;
+use serde::{Deserialize, Serialize};
+
+
+// 文件发送配置
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct SendFileOption {
+    // 每包字节数
+    pub chunk_size: usize,
+    // 包间延时，毫秒
+    pub delay: u64,
+    // 限速，字节每秒，设置后忽略包间延时
+    pub bps: Option<u64>,
+}
+
+impl Default for SendFileOption {
+    fn default() -> Self {
+        Self {
+            chunk_size: 256,
+            delay: 0,
+            bps: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TransferState {
+    Sending,
+    Paused,
+    Done,
+    Cancel,
+    Error,
+}
+
+// 传输进度，通过 send_file_{id} 事件发往前端
+#[derive(Clone, Debug, Serialize)]
+pub struct TransferProgress {
+    pub state: TransferState,
+    pub sent: u64,
+    pub total: u64,
+    pub msg: String,
+}
+
+impl TransferProgress {
+    pub fn new(state: TransferState, sent: u64, total: u64) -> Self {
+        Self { state, sent, total, msg: String::new() }
+    }
+
+    pub fn error(sent: u64, total: u64, msg: impl Into<String>) -> Self {
+        Self { state: TransferState::Error, sent, total, msg: msg.into() }
+    }
+}
+
+// 分包发送文件，每写入一包调用一次 on_chunk(本包数据, 已发送, 总大小)
+// 返回实际发送的字节数，被取消时提前返回
+pub fn send_file(
+    port: &mut dyn Write,
+    path: &str,
+    option: &SendFileOption,
+    cancel: &AtomicBool,
+    mut on_chunk: impl FnMut(&[u8], u64, u64),
+) -> Result<u64> {
+    let mut file = File::open(path).with_context(|| format!("打开文件失败：{path}"))?;
+    let total = file.metadata()?.len();
+    let mut buf = vec![0u8; option.chunk_size.max(1)];
+    let mut sent = 0u64;
+    let start = Instant::now();
+
+    while !cancel.load(Ordering::Relaxed) {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        port.write_all(&buf[..n]).context("写入串口失败")?;
+        port.flush()?;
+        sent += n as u64;
+        on_chunk(&buf[..n], sent, total);
+
+        if sent >= total {
+            break;
+        }
+        // 限速优先，按已发送量计算应耗时间
+        match option.bps {
+            Some(bps) if bps > 0 => {
+                let expect = Duration::from_secs_f64(sent as f64 / bps as f64);
+                let elapsed = start.elapsed();
+                if expect > elapsed {
+                    thread::sleep(expect - elapsed);
+                }
+            }
+            _ => {
+                if option.delay > 0 {
+                    thread::sleep(Duration::from_millis(option.delay));
+                }
+            }
+        }
+    }
+
+    Ok(sent)
+}
+
+
+type WriteHook = Box<dyn FnMut(&[u8]) + Send>;
+
+// 协议传输期间接管串口：读取来自接收线程转发的数据，写入直接发往串口
+pub struct TapPort {
+    port: Box<dyn SerialPort>,
+    rx: Receiver<Vec<u8>>,
+    pending: Vec<u8>,
+    timeout: Duration,
+    // 每次写入串口后调用，用于记录发送数据
+    on_write: WriteHook,
+}
+
+impl TapPort {
+    pub fn new(port: Box<dyn SerialPort>, rx: Receiver<Vec<u8>>) -> Self {
+        Self {
+            port,
+            rx,
+            pending: Vec::new(),
+            timeout: Duration::from_millis(100),
+            on_write: Box::new(|_| {}),
+        }
+    }
+
+    pub fn set_on_write(&mut self, on_write: impl FnMut(&[u8]) + Send + 'static) {
+        self.on_write = Box::new(on_write);
+    }
+}
+
+impl Read for TapPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pending.is_empty() {
+            match self.rx.recv_timeout(self.timeout) {
+                Ok(v) => self.pending = v,
+                Err(RecvTimeoutError::Timeout) => return Err(io::ErrorKind::TimedOut.into()),
+                Err(RecvTimeoutError::Disconnected) => return Err(io::Error::new(io::ErrorKind::BrokenPipe, "串口已断开")),
+            }
+        }
+        let n = buf.len().min(self.pending.len());
+        buf[..n].copy_from_slice(&self.pending[..n]);
+        self.pending.drain(..n);
+        Ok(n)
+    }
+}
+
+impl Write for TapPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.port.write(buf)?;
+        (self.on_write)(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.port.flush()
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn temp_file(name: &str, len: usize) -> String {
+        let path = std::env::temp_dir().join(name);
+        let data = (0..len).map(|v| v as u8).collect::<Vec<u8>>();
+        std::fs::write(&path, data).unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn test_send_file_chunks() {
+        let path = temp_file("multi_tools_send_file_chunks.bin", 1000);
+        let option = SendFileOption { chunk_size: 300, ..Default::default() };
+        let cancel = AtomicBool::new(false);
+        let mut out = Vec::new();
+        let mut chunks = Vec::new();
+        let sent = send_file(&mut out, &path, &option, &cancel, |v, s, t| {
+            chunks.push((v.len(), s, t));
+        }).unwrap();
+
+        assert_eq!(sent, 1000);
+        assert_eq!(out.len(), 1000);
+        assert_eq!(chunks, vec![(300, 300, 1000), (300, 600, 1000), (300, 900, 1000), (100, 1000, 1000)]);
+    }
+
+    #[test]
+    fn test_send_file_bps() {
+        let path = temp_file("multi_tools_send_file_bps.bin", 400);
+        let option = SendFileOption { chunk_size: 100, bps: Some(2000), ..Default::default() };
+        let cancel = AtomicBool::new(false);
+        let start = Instant::now();
+        send_file(&mut Vec::new(), &path, &option, &cancel, |_, _, _| {}).unwrap();
+        // 前三包之后各需等待到 50ms、100ms、150ms
+        assert!(start.elapsed() >= Duration::from_millis(150));
+    }
+
+    #[test]
+    fn test_send_file_cancel() {
+        let path = temp_file("multi_tools_send_file_cancel.bin", 1000);
+        let option = SendFileOption { chunk_size: 100, ..Default::default() };
+        let cancel = AtomicBool::new(false);
+        let sent = send_file(&mut Vec::new(), &path, &option, &cancel, |_, s, _| {
+            if s >= 200 {
+                cancel.store(true, Ordering::Relaxed);
+            }
+        }).unwrap();
+        assert_eq!(sent, 200);
+    }
+}
@@ -0,0 +1,186 @@
+use std::collections::VecDeque;
+use chrono::{DateTime, Local};
+use encoding_rs::Encoding;
+use serde::{Deserialize, Serialize};
+use crate::codec::StreamDecoder;
+use crate::search::{Matcher, SearchQuery, StreamMatcher};
+
+
+// 自动应答规则，接收数据匹配时发送 reply
+#[derive(Clone, Debug, Deserialize)]
+pub struct TriggerRule {
+    pub name: String,
+    pub query: SearchQuery,
+    // 应答内容，hex 为 true 时按十六进制解析，否则按转义文本解析
+    pub reply: String,
+    #[serde(default)]
+    pub hex: bool,
+    // 匹配后延时发送，毫秒
+    #[serde(default)]
+    pub delay: u64,
+    // 最多触发次数，None 表示不限
+    #[serde(default)]
+    pub max: Option<u32>,
+}
+
+// 规则命中，通过 trigger_{id} 事件发往前端并保存在历史中
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct TriggerFire {
+    pub index: usize,
+    pub name: String,
+    // 已触发次数，含本次
+    pub count: u32,
+    // 匹配到的内容
+    pub text: String,
+    pub time: String,
+    #[serde(skip)]
+    pub reply: Vec<u8>,
+    #[serde(skip)]
+    pub delay: u64,
+}
+
+// 命中历史上限，超出后丢弃最早的记录
+const HISTORY_LIMIT: usize = 1000;
+
+#[derive(Debug)]
+struct Trigger {
+    rule: TriggerRule,
+    matcher: StreamMatcher,
+    reply: Vec<u8>,
+    fired: u32,
+}
+
+// 单个会话的全部规则与命中历史
+#[derive(Debug)]
+pub struct TriggerSet {
+    triggers: Vec<Trigger>,
+    decoder: StreamDecoder,
+    history: VecDeque<TriggerFire>,
+}
+
+impl TriggerSet {
+    // rules 为 (规则, 匹配器, 应答数据)，文本按 code 解码后匹配
+    pub fn new(rules: Vec<(TriggerRule, Matcher, Vec<u8>)>, code: &'static Encoding) -> Self {
+        Self {
+            triggers: rules.into_iter().map(|(rule, matcher, reply)| Trigger {
+                rule,
+                matcher: StreamMatcher::new(matcher),
+                reply,
+                fired: 0,
+            }).collect(),
+            decoder: StreamDecoder::new(code),
+            history: VecDeque::new(),
+        }
+    }
+
+    // 替换规则，保留命中历史
+    pub fn set_rules(&mut self, rules: Vec<(TriggerRule, Matcher, Vec<u8>)>, code: &'static Encoding) {
+        let history = std::mem::take(&mut self.history);
+        *self = Self::new(rules, code);
+        self.history = history;
+    }
+
+    pub fn history(&self) -> Vec<TriggerFire> {
+        self.history.iter().cloned().collect()
+    }
+
+    pub fn clear_history(&mut self) {
+        self.history.clear();
+    }
+
+    // 输入接收数据，返回本次命中的规则，匹配过的内容不会重复触发
+    pub fn feed(&mut self, data: &[u8], time: DateTime<Local>) -> Vec<TriggerFire> {
+        let text = self.decoder.decode(data);
+        let mut fires = Vec::new();
+        for (index, t) in self.triggers.iter_mut().enumerate() {
+            if t.rule.max.is_some_and(|v| t.fired >= v) {
+                continue;
+            }
+            t.matcher.push(&text, data);
+            while let Some(v) = t.matcher.next_match() {
+                // 空匹配不消耗内容，继续循环会一直命中
+                if v.is_empty() {
+                    break;
+                }
+                t.fired += 1;
+                fires.push(TriggerFire {
+                    index,
+                    name: t.rule.name.clone(),
+                    count: t.fired,
+                    text: v,
+                    time: time.format("%Y-%m-%d %H:%M:%S%.6f").to_string(),
+                    reply: t.reply.clone(),
+                    delay: t.rule.delay,
+                });
+                if t.rule.max.is_some_and(|v| t.fired >= v) {
+                    break;
+                }
+            }
+        }
+        for v in &fires {
+            if self.history.len() >= HISTORY_LIMIT {
+                self.history.pop_front();
+            }
+            self.history.push_back(v.clone());
+        }
+        fires
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::search::SearchKind;
+
+    fn rule(name: &str, kind: SearchKind, pattern: &str, max: Option<u32>) -> (TriggerRule, Matcher, Vec<u8>) {
+        let query = SearchQuery { kind, pattern: pattern.to_string(), ignore_case: false };
+        let matcher = Matcher::new(&query).unwrap();
+        let rule = TriggerRule { name: name.to_string(), query, reply: String::new(), hex: false, delay: 0, max };
+        (rule, matcher, name.as_bytes().to_vec())
+    }
+
+    impl TriggerSet {
+        fn feed_now(&mut self, data: &[u8]) -> Vec<TriggerFire> {
+            self.feed(data, Local::now())
+        }
+    }
+
+    #[test]
+    fn test_trigger_feed() {
+        let mut set = TriggerSet::new(vec![
+            rule("login", SearchKind::Regex, r"login:\s*$", None),
+            rule("sync", SearchKind::Hex, "AA 55", Some(2)),
+        ], encoding_rs::UTF_8);
+
+        // 跨两次读取的匹配
+        assert!(set.feed_now(b"log").is_empty());
+        let fires = set.feed_now(b"in: ");
+        assert_eq!(fires.iter().map(|v| (v.index, v.count)).collect::<Vec<_>>(), vec![(0, 1)]);
+        assert_eq!(fires[0].reply, b"login");
+        // 已匹配的内容不再触发
+        assert!(set.feed_now(b"x").is_empty());
+
+        let fires = set.feed_now(&[0xAA, 0x55, 0x00, 0xAA]);
+        assert_eq!(fires.iter().map(|v| (v.index, v.count)).collect::<Vec<_>>(), vec![(1, 1)]);
+        let fires = set.feed_now(&[0x55, 0xAA, 0x55]);
+        assert_eq!(fires.iter().map(|v| (v.index, v.count)).collect::<Vec<_>>(), vec![(1, 2)]);
+        // 达到次数上限
+        assert!(set.feed_now(&[0xAA, 0x55]).is_empty());
+
+        // 命中记入历史，替换规则后保留
+        assert_eq!(set.history().iter().map(|v| (v.name.as_str(), v.text.as_str())).collect::<Vec<_>>(),
+                   vec![("login", "login: "), ("sync", "AA 55"), ("sync", "AA 55")]);
+        set.set_rules(Vec::new(), encoding_rs::UTF_8);
+        assert_eq!(set.history().len(), 3);
+        set.clear_history();
+        assert!(set.history().is_empty());
+    }
+
+    #[test]
+    fn test_trigger_empty_match() {
+        let mut set = TriggerSet::new(vec![rule("any", SearchKind::Regex, r"\d*", None)], encoding_rs::UTF_8);
+        assert!(set.feed_now(b"abc").is_empty());
+        assert!(set.history().is_empty());
+    }
+}